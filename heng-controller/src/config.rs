@@ -1,8 +1,9 @@
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
+use ubyte::ByteUnit;
 use validator::Validate;
 
 #[derive(Debug, Clone, Validate, Serialize, Deserialize)]
@@ -18,12 +19,22 @@ pub struct Config {
 
     #[validate]
     pub auth: Auth,
+
+    #[validate]
+    pub files: Files,
+
+    #[validate]
+    pub callback: Callback,
 }
 
 #[derive(Debug, Clone, Validate, Serialize, Deserialize)]
 pub struct Server {
     #[validate(length(min = 1))]
     pub address: String,
+
+    /// how long callbacks are flushed on shutdown
+    #[validate(range(max = 600000))]
+    pub shutdown_timeout: u64, // ms
 }
 
 #[derive(Debug, Clone, Validate, Serialize, Deserialize)]
@@ -42,6 +53,45 @@ pub struct Judger {
 
     #[validate(range(min = 1000, max = 60000))]
     pub rpc_timeout: u64, // ms
+
+    #[validate(range(min = 1, max = 65536))]
+    pub rpc_max_inflight: usize,
+
+    /// how long a task waits for a judger which has cached its data
+    #[validate(range(max = 60000))]
+    pub locality_wait: u64, // ms
+
+    /// a dispatched task fails if its judger does not report the result in time
+    #[validate(range(min = 10000))]
+    pub task_timeout: u64, // ms
+}
+
+#[derive(Debug, Clone, Validate, Serialize, Deserialize)]
+pub struct Files {
+    pub directory: PathBuf,
+
+    /// the max size of an uploaded file
+    pub max_size: ByteUnit,
+
+    /// unreferenced files are removed after not being used for `ttl`
+    pub ttl: u64, // ms
+
+    #[validate(range(min = 1000))]
+    pub gc_interval: u64, // ms
+}
+
+#[derive(Debug, Clone, Validate, Serialize, Deserialize)]
+pub struct Callback {
+    #[validate(range(min = 100, max = 60000))]
+    pub timeout: u64, // ms
+
+    /// how many times a failed callback is retried
+    #[validate(range(max = 16))]
+    pub max_retries: u32,
+
+    /// the delay before the first retry, which is doubled after each retry
+    #[validate(range(min = 10, max = 60000))]
+    pub retry_delay: u64, // ms
 }
 
 #[derive(Debug, Clone, Validate, Serialize, Deserialize)]
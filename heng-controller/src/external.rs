@@ -1,19 +1,94 @@
+use crate::config::Config;
 use crate::redis::{Connection, RedisModule};
 
 use heng_protocol::external::CreateJudgeRequest;
 use mobc_redis::redis;
 
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::Result;
+use serde::Serialize;
+use tokio::sync::Notify;
+use tokio::{task, time};
+use tracing::{error, info, warn};
 
 pub struct ExternalModule {
     redis_module: Arc<RedisModule>,
+    http_client: reqwest::Client,
+    max_retries: u32,
+    retry_delay: Duration,
+    /// tasks which deliver callbacks
+    outboxes: AtomicUsize,
+    flushed: Notify,
 }
 
 impl ExternalModule {
-    pub fn new(redis_module: Arc<RedisModule>) -> Self {
-        Self { redis_module }
+    pub fn new(config: &Config, redis_module: Arc<RedisModule>) -> Result<Self> {
+        let http_client = reqwest::Client::builder()
+            .timeout(Duration::from_millis(config.callback.timeout))
+            .build()?;
+        Ok(Self {
+            redis_module,
+            http_client,
+            max_retries: config.callback.max_retries,
+            retry_delay: Duration::from_millis(config.callback.retry_delay),
+            outboxes: AtomicUsize::new(0),
+            flushed: Notify::new(),
+        })
+    }
+
+    /// spawns a task which delivers callbacks, which is waited on shutdown
+    pub fn spawn_outbox(self: &Arc<Self>, outbox: impl Future<Output = ()> + Send + 'static) {
+        self.outboxes.fetch_add(1, Relaxed);
+        let this = Arc::clone(self);
+        task::spawn(async move {
+            outbox.await;
+            if this.outboxes.fetch_sub(1, Relaxed) == 1 {
+                this.flushed.notify_one();
+            }
+        });
+    }
+
+    /// waits for all outboxes to be flushed
+    ///
+    /// An outbox is flushed after its callback channel is closed and drained.
+    pub async fn flush_outboxes(&self, timeout: Duration) {
+        let flush = async {
+            while self.outboxes.load(Relaxed) > 0 {
+                self.flushed.notified().await;
+            }
+        };
+        info!(outboxes = self.outboxes.load(Relaxed), "flushing callbacks");
+        if time::timeout(timeout, flush).await.is_err() {
+            warn!(
+                outboxes = self.outboxes.load(Relaxed),
+                "flush timeout, drop callbacks"
+            );
+        }
+    }
+
+    /// posts a callback, retries with exponential backoff on failure
+    pub async fn post_callback<T: Serialize>(&self, url: &str, task_id: &str, body: &T) {
+        let mut delay = self.retry_delay;
+        let mut retries = 0;
+        loop {
+            let res = self.http_client.post(url).json(body).send().await;
+            let err = match res.and_then(|res| res.error_for_status()) {
+                Ok(_) => return,
+                Err(err) => err,
+            };
+            if retries >= self.max_retries {
+                error!(%url, %task_id, %err, "failed to post callback, drop it");
+                return;
+            }
+            warn!(%url, %task_id, %err, ?delay, "failed to post callback, retry");
+            time::sleep(delay).await;
+            delay *= 2;
+            retries += 1;
+        }
     }
 
     async fn get_redis_connection(&self) -> Result<Connection> {
@@ -0,0 +1,484 @@
+use crate::Config;
+
+use heng_protocol::common::{DynamicFile, File, Judge};
+use heng_protocol::error::ErrorCode;
+use heng_protocol::internal::ErrorInfo;
+use heng_utils::crypto::{Hashsum, Hasher};
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use anyhow::Result;
+use dashmap::DashMap;
+use tokio::io::AsyncWriteExt;
+use tokio::time;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+/// a content-addressed file store
+///
+/// Blobs are named by [`Hashsum::file_name`] of their hashsums. A blob is removed when it is
+/// not referenced by any task and has not been used for `ttl`.
+pub struct FileModule {
+    directory: PathBuf,
+    max_size: u64,
+    ttl: Duration,
+    gc_interval: Duration,
+    blobs: DashMap<String, Blob>,
+}
+
+struct Blob {
+    refs: usize,
+    last_used: SystemTime,
+}
+
+impl FileModule {
+    pub fn new(config: &Config) -> Result<Self> {
+        let directory = config.files.directory.clone();
+        fs::create_dir_all(&directory)?;
+
+        // index blobs on disk and remove partial uploads
+        let blobs = DashMap::new();
+        for entry in fs::read_dir(&directory)? {
+            let entry = entry?;
+            let name = entry.file_name().into_string().ok();
+            let is_blob = |name: &str| {
+                Hashsum::parse_file_name(name).map_or(false, |h| h.algorithm.is_addressable())
+            };
+            match name {
+                Some(name) if is_blob(&name) => {
+                    let last_used = entry.metadata()?.modified()?;
+                    blobs.insert(name, Blob { refs: 0, last_used });
+                }
+                _ => {
+                    if let Err(err) = fs::remove_file(entry.path()) {
+                        warn!(path = %entry.path().display(), %err, "failed to remove file");
+                    }
+                }
+            }
+        }
+        info!(count = blobs.len(), "file store loaded");
+
+        Ok(Self {
+            directory,
+            max_size: config.files.max_size.as_u64(),
+            ttl: Duration::from_millis(config.files.ttl),
+            gc_interval: Duration::from_millis(config.files.gc_interval),
+            blobs,
+        })
+    }
+
+    pub fn max_size(&self) -> u64 {
+        self.max_size
+    }
+
+    /// starts to store a blob, which is deduplicated by its hashsum
+    ///
+    /// `name` is `{algorithm}:{hex}`, `{algorithm}-{hex}` or bare sha256 hex,
+    /// of sha256 or blake3.
+    pub async fn upload(&self, name: &str) -> Result<Upload<'_>> {
+        let hashsum = match parse_blob_name(name) {
+            Some(h) => h,
+            None => return Err(invalid_hashsum(name)),
+        };
+
+        // the content is written to a unique temporary file, then published by an atomic rename
+        let name = hashsum.file_name();
+        let temp_path = self
+            .directory
+            .join(format!("{}.tmp-{}", name, Uuid::new_v4()));
+        let file = tokio::fs::File::create(&temp_path).await?;
+
+        Ok(Upload {
+            module: self,
+            name,
+            hashsum: hashsum.to_string(),
+            expected: hashsum.hex.to_owned(),
+            hasher: Some(Hasher::new(hashsum.algorithm)),
+            file,
+            temp_path,
+            size: 0,
+        })
+    }
+
+    /// returns the path of a blob if it exists
+    pub fn get(&self, name: &str) -> Option<PathBuf> {
+        let name = parse_blob_name(name)?.file_name();
+        let mut blob = self.blobs.get_mut(&name)?;
+        blob.last_used = SystemTime::now();
+        Some(self.path(&name))
+    }
+
+    /// references blobs used by a task, which are kept until released
+    ///
+    /// `hashsums` are names returned by [`stored_files`].
+    pub fn retain(&self, hashsums: &[String]) -> Result<()> {
+        for (i, hashsum) in hashsums.iter().enumerate() {
+            match self.blobs.get_mut(hashsum.as_str()) {
+                Some(mut blob) => {
+                    blob.refs += 1;
+                    blob.last_used = SystemTime::now();
+                }
+                None => {
+                    self.release(&hashsums[..i]);
+                    return Err(ErrorInfo {
+                        code: ErrorCode::NotFound,
+                        message: Some(format!("file not found: {}", hashsum)),
+                    }
+                    .into());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn release(&self, hashsums: &[String]) {
+        for hashsum in hashsums {
+            if let Some(mut blob) = self.blobs.get_mut(hashsum.as_str()) {
+                blob.refs = blob.refs.saturating_sub(1);
+                blob.last_used = SystemTime::now();
+            }
+        }
+    }
+
+    pub async fn gc_loop(self: Arc<Self>) {
+        loop {
+            time::sleep(self.gc_interval).await;
+            self.gc();
+        }
+    }
+
+    /// removes blobs which are unreferenced and expired
+    ///
+    /// A blob is unlinked under its entry lock, so that a concurrent `put` either
+    /// refreshes it before the removal or stores it again after the removal.
+    fn gc(&self) {
+        let now = SystemTime::now();
+        let ttl = self.ttl;
+        let is_garbage = |blob: &Blob| {
+            let expired = match now.duration_since(blob.last_used) {
+                Ok(idle) => idle >= ttl,
+                Err(_) => false,
+            };
+            blob.refs == 0 && expired
+        };
+
+        let candidates: Vec<String> = self
+            .blobs
+            .iter()
+            .filter(|entry| is_garbage(entry.value()))
+            .map(|entry| entry.key().clone())
+            .collect();
+
+        for hashsum in candidates {
+            self.blobs.remove_if(&hashsum, |_, blob| {
+                if !is_garbage(blob) {
+                    return false;
+                }
+                let path = self.path(&hashsum);
+                match fs::remove_file(&path) {
+                    Ok(()) => debug!(%hashsum, "file expired"),
+                    Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                    Err(err) => {
+                        warn!(path = %path.display(), %err, "failed to remove file");
+                        return false;
+                    }
+                }
+                true
+            });
+        }
+    }
+
+    fn path(&self, hashsum: &str) -> PathBuf {
+        self.directory.join(hashsum)
+    }
+}
+
+/// a blob being stored, whose content is hashed while it is written to a temporary file
+///
+/// The temporary file is removed unless the blob is published by [`Upload::commit`].
+pub struct Upload<'a> {
+    module: &'a FileModule,
+    name: String,
+    hashsum: String,
+    expected: String,
+    /// taken by `commit`
+    hasher: Option<Hasher>,
+    file: tokio::fs::File,
+    temp_path: PathBuf,
+    size: u64,
+}
+
+impl Upload<'_> {
+    pub async fn write(&mut self, chunk: &[u8]) -> Result<()> {
+        self.size += chunk.len() as u64;
+        if self.size > self.module.max_size {
+            return Err(ErrorInfo {
+                code: ErrorCode::InvalidRequest,
+                message: Some(format!("file is larger than {} bytes", self.module.max_size)),
+            }
+            .into());
+        }
+        if let Some(ref mut hasher) = self.hasher {
+            hasher.update(chunk);
+        }
+        self.file.write_all(chunk).await?;
+        Ok(())
+    }
+
+    /// verifies the hashsum of the content, then publishes the blob
+    ///
+    /// The content of a blob which is already stored is discarded.
+    pub async fn commit(mut self) -> Result<()> {
+        self.file.flush().await?;
+
+        let content_hash = self.hasher.take().map(Hasher::finalize_hex);
+        if content_hash.as_deref() != Some(&*self.expected) {
+            return Err(ErrorInfo {
+                code: ErrorCode::InvalidRequest,
+                message: Some(format!(
+                    "file hashsum mismatch: content = {}",
+                    content_hash.unwrap_or_default()
+                )),
+            }
+            .into());
+        }
+
+        let module = self.module;
+        if let Some(mut blob) = module.blobs.get_mut(&self.name) {
+            blob.last_used = SystemTime::now();
+            return Ok(());
+        }
+
+        tokio::fs::rename(&self.temp_path, module.path(&self.name)).await?;
+        module.blobs.entry(self.name.clone()).or_insert(Blob {
+            refs: 0,
+            last_used: SystemTime::now(),
+        });
+        debug!(hashsum = %self.hashsum, size = self.size, "file stored");
+        Ok(())
+    }
+}
+
+impl Drop for Upload<'_> {
+    fn drop(&mut self) {
+        match fs::remove_file(&self.temp_path) {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => warn!(path = %self.temp_path.display(), %err, "failed to remove file"),
+        }
+    }
+}
+
+/// names of stored files referenced by a judge
+///
+/// Binary files are rejected, since they are only produced for judgers with the msgpack codec.
+pub fn stored_files(
+    data: Option<&File>,
+    dynamic_files: Option<&[DynamicFile]>,
+    judge: &Judge,
+) -> Result<Vec<String>> {
+    let mut files: Vec<&File> = Vec::new();
+    files.extend(data);
+    for dyn_file in dynamic_files.into_iter().flatten() {
+        if let DynamicFile::Remote { file, .. } = dyn_file {
+            files.push(file);
+        }
+    }
+    files.extend(judge.executables().into_iter().map(|e| &e.source));
+
+    let mut hashsums = Vec::new();
+    for file in files {
+        match file {
+            File::Stored { hashsum } => {
+                match Hashsum::parse(hashsum).filter(|h| h.algorithm.is_addressable()) {
+                    Some(h) => hashsums.push(h.file_name()),
+                    None => return Err(invalid_hashsum(hashsum)),
+                }
+            }
+            File::Binary { .. } => {
+                return Err(ErrorInfo {
+                    code: ErrorCode::InvalidRequest,
+                    message: Some("binary files are not accepted".to_owned()),
+                }
+                .into())
+            }
+            File::Url { .. } | File::Direct { .. } => {}
+        }
+    }
+    hashsums.sort();
+    hashsums.dedup();
+    Ok(hashsums)
+}
+
+/// the start of `Range: bytes={start}-`, which is ignored if it is invalid or out of range
+pub fn range_start(range: Option<&str>, len: u64) -> Option<u64> {
+    range
+        .and_then(|r| r.strip_prefix("bytes="))
+        .and_then(|r| r.strip_suffix('-'))
+        .and_then(|r| r.parse::<u64>().ok())
+        .filter(|&start| start < len)
+}
+
+/// parses a blob name in either form of hashsums, whose algorithm must be addressable
+///
+/// Urls may name a blob by [`Hashsum::file_name`], or by the hashsum of `File::Stored`.
+fn parse_blob_name(name: &str) -> Option<Hashsum<'_>> {
+    Hashsum::parse(name)
+        .or_else(|| Hashsum::parse_file_name(name))
+        .filter(|h| h.algorithm.is_addressable())
+}
+
+fn invalid_hashsum(hashsum: &str) -> anyhow::Error {
+    ErrorInfo {
+        code: ErrorCode::InvalidRequest,
+        message: Some(format!("invalid file hashsum: {}", hashsum)),
+    }
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use heng_protocol::common::{Environment, Executable, Limit};
+    use heng_utils::crypto::{hex_digest, HashAlgorithm};
+    use heng_utils::temp_dir::TempDir;
+
+    fn file_module(dir: &TempDir, ttl: Duration) -> FileModule {
+        FileModule {
+            directory: dir.path().to_owned(),
+            max_size: 1024,
+            ttl,
+            gc_interval: Duration::from_secs(60),
+            blobs: DashMap::new(),
+        }
+    }
+
+    fn file_count(dir: &TempDir) -> usize {
+        fs::read_dir(dir.path()).unwrap().count()
+    }
+
+    /// uploads the content in two chunks
+    async fn put(files: &FileModule, name: &str, content: &[u8]) -> Result<()> {
+        let mut upload = files.upload(name).await?;
+        let (head, tail) = content.split_at(content.len() / 2);
+        upload.write(head).await?;
+        upload.write(tail).await?;
+        upload.commit().await
+    }
+
+    #[tokio::test]
+    async fn put_and_dedup() {
+        let dir = TempDir::new("heng-files").unwrap();
+        let files = file_module(&dir, Duration::from_secs(60));
+
+        let content = b"1 2";
+        let sha256 = hex_digest(HashAlgorithm::Sha256, content);
+        let blake3_hex = hex_digest(HashAlgorithm::Blake3, content);
+        let blake3 = format!("blake3-{}", blake3_hex);
+
+        put(&files, &sha256, content).await.unwrap();
+        put(&files, &sha256, content).await.unwrap();
+        put(&files, &blake3, content).await.unwrap();
+        assert_eq!(file_count(&dir), 2);
+        assert_eq!(fs::read(files.get(&blake3).unwrap()).unwrap(), b"1 2");
+
+        // the form of `File::Stored` names the same blob
+        let stored = format!("blake3:{}", blake3_hex);
+        put(&files, &stored, content).await.unwrap();
+        assert_eq!(files.get(&stored), files.get(&blake3));
+        assert_eq!(file_count(&dir), 2);
+
+        // mismatched content, oversized content and invalid names are rejected,
+        // and nothing is left
+        let other = hex_digest(HashAlgorithm::Sha256, b"3");
+        assert!(put(&files, &other, content).await.is_err());
+        assert!(put(&files, &sha256, &[b'x'; 2048]).await.is_err());
+        assert!(put(&files, "../x", content).await.is_err());
+
+        // an abandoned upload is removed
+        let mut upload = files.upload(&other).await.unwrap();
+        upload.write(b"3").await.unwrap();
+        drop(upload);
+
+        // md5 is not collision resistant, so it never addresses a blob
+        let md5 = format!("md5-{}", hex_digest(HashAlgorithm::Md5, content));
+        assert!(put(&files, &md5, content).await.is_err());
+        assert!(files.get(&md5).is_none());
+        assert!(files.get(&other).is_none());
+        assert_eq!(file_count(&dir), 2);
+    }
+
+    #[tokio::test]
+    async fn retain_and_gc() {
+        let dir = TempDir::new("heng-files").unwrap();
+        let files = file_module(&dir, Duration::from_secs(0));
+
+        let content = b"1 2";
+        let name = hex_digest(HashAlgorithm::Sha256, content);
+        put(&files, &name, content).await.unwrap();
+
+        // a missing file releases the files retained before it
+        let missing = hex_digest(HashAlgorithm::Sha256, b"3");
+        let err = files.retain(&[name.clone(), missing]).unwrap_err();
+        assert_eq!(
+            err.downcast::<ErrorInfo>().unwrap().code,
+            ErrorCode::NotFound
+        );
+        assert_eq!(files.blobs.get(&name).unwrap().refs, 0);
+
+        files.retain(&[name.clone()]).unwrap();
+        files.gc();
+        assert!(files.get(&name).is_some());
+
+        files.release(&[name.clone()]);
+        files.gc();
+        assert!(files.get(&name).is_none());
+        assert_eq!(file_count(&dir), 0);
+
+        // a collected file can be stored again
+        put(&files, &name, content).await.unwrap();
+        assert!(files.get(&name).is_some());
+        assert_eq!(file_count(&dir), 1);
+    }
+
+    #[test]
+    fn range() {
+        assert_eq!(range_start(Some("bytes=3-"), 10), Some(3));
+        assert_eq!(range_start(Some("bytes=0-"), 10), Some(0));
+        assert_eq!(range_start(Some("bytes=10-"), 10), None);
+        assert_eq!(range_start(Some("bytes=3-5"), 10), None);
+        assert_eq!(range_start(Some("3-"), 10), None);
+        assert_eq!(range_start(None, 10), None);
+    }
+
+    #[test]
+    fn reject_binary_files() {
+        let data = File::Binary {
+            content: b"1 2".to_vec(),
+            hashsum: None,
+        };
+        let judge = Judge::Normal {
+            user: Executable {
+                source: File::Direct {
+                    content: String::new(),
+                    hashsum: None,
+                    base64: false,
+                },
+                environment: Environment {
+                    language: "c".to_owned(),
+                    system: "Linux".to_owned(),
+                    arch: "x64".to_owned(),
+                    options: serde_json::Map::new(),
+                },
+                limit: Limit::default(),
+            },
+        };
+        assert!(stored_files(None, None, &judge).unwrap().is_empty());
+        assert!(stored_files(Some(&data), None, &judge).is_err());
+    }
+}
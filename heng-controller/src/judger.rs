@@ -1,38 +1,75 @@
 use crate::Config;
 
 use heng_utils::container::inject;
+use heng_utils::crypto::Hashsum;
 use heng_utils::queue::Queue;
 
 use heng_protocol::common as hp_common;
 use heng_protocol::error::ErrorCode;
+use heng_protocol::external::UpdateJudgeCallback;
+use heng_protocol::internal::codec::{self, Codec, Frame};
+use heng_protocol::internal::rpc::{RpcConfig, RpcSession};
 use heng_protocol::internal::ws_json::{
-    CreateJudgeArgs, Message as RpcMessage, Request as RpcRequest, Response as RpcResponse,
+    CreateJudgeArgs, Message as RpcMessage, ReportStatusArgs, Request as RpcRequest,
+    Response as RpcResponse,
 };
-use heng_protocol::internal::ErrorInfo;
+use heng_protocol::internal::{ConnectionSettings, Feature, PartialConnectionSettings};
 
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::mem;
-use std::sync::atomic::AtomicU32;
-use std::sync::atomic::Ordering::Relaxed;
-use std::sync::{Arc, Weak};
+use std::sync::atomic::{AtomicU64, Ordering::Relaxed};
+use std::sync::{Arc, Mutex, Weak};
 use std::time::Duration;
 
 use anyhow::{format_err, Result};
-use chrono::Utc;
 use dashmap::DashMap;
 use futures::stream::SplitStream;
 use futures::{StreamExt, TryFutureExt};
-use tokio::sync::{mpsc, oneshot, RwLock};
+use serde::Serialize;
+use tokio::sync::{mpsc, oneshot, watch, RwLock};
 use tokio::task::{self, JoinHandle};
 use tokio::time;
 use tokio_stream::wrappers::ReceiverStream;
-use tracing::{debug, error, warn};
+use tracing::{debug, error, info, warn};
 use uuid::Uuid;
 use warp::ws::{self, WebSocket};
 
+/// features implemented by the controller
+pub const FEATURES: &[Feature] = &[
+    Feature::StatusReport,
+    Feature::MessagePack,
+    Feature::JudgeProgress,
+    Feature::Drain,
+];
+
 pub struct JudgerModule {
     judger_map: RwLock<HashMap<Arc<str>, Arc<Judger>>>,
     available_queue: Queue<Weak<Judger>>,
+    /// running tasks, which outlive the session they are dispatched by
+    ///
+    /// A reconnected judger reports results of tasks dispatched before.
+    tasks: DashMap<Arc<str>, TaskCallbacks>,
+    /// wakes tasks which no free judger supports, when a slot is released
+    /// or a judger comes online
+    parked_tasks: Mutex<Vec<oneshot::Sender<()>>>,
+    /// cancels scheduling tasks, which hold callbacks of their tasks
+    shutdown_tx: watch::Sender<bool>,
+    shutdown_rx: watch::Receiver<bool>,
+    /// dispatches of tasks with hashsummed data
+    local_dispatches: AtomicU64,
+    remote_dispatches: AtomicU64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Metrics {
+    /// dispatches to a judger which has cached the data
+    pub local_dispatches: u64,
+    pub remote_dispatches: u64,
+    /// data cache hits and misses reported by online judgers
+    pub data_cache_hits: u64,
+    pub data_cache_misses: u64,
+    pub data_cache_hit_rate: Option<f64>,
 }
 
 pub struct Judger {
@@ -40,49 +77,72 @@ pub struct Judger {
     ws_id: Arc<str>,
     info: JudgerInfo,
     state: RwLock<JudgerState>,
-    rpc_timeout: u64,
-    tasks: DashMap<Arc<str>, (UpdateCallbackSender, FinishCallbackSender)>,
+    rpc_config: RpcConfig,
+    ws_sender: RwLock<Option<mpsc::Sender<ws::Message>>>,
+    last_report: RwLock<Option<ReportStatusArgs>>,
 }
 
 #[derive(Debug)]
 pub struct JudgerInfo {
+    /// the identity of the judger, which is kept across sessions
+    pub access_key: Arc<str>,
+    pub instance_id: Arc<str>,
     pub max_task_count: u32,
     pub name: Option<String>,
     pub core_count: Option<u32>,
     pub system_info: Option<String>,
+    pub protocol_version: u32,
+    pub features: Vec<Feature>,
+    /// languages which passed the self-test, or unknown for old judgers
+    pub languages: Option<Vec<String>>,
 }
 
 enum JudgerState {
     Registered { remove_task: JoinHandle<()> },
-    Online(Arc<WsSession>),
-    Disabled(Arc<WsSession>),
+    Online(Arc<RpcSession>),
+    Disabled(Arc<RpcSession>),
     Offline,
 }
 
-struct WsSession {
-    seq: AtomicU32,
-    callbacks: DashMap<u32, oneshot::Sender<RpcResponse>>,
-    sender: mpsc::Sender<ws::Message>,
-}
-
 pub struct JudgeTask {
     pub id: Arc<str>,
     pub data: Option<hp_common::File>,
     pub dynamic_files: Option<Vec<hp_common::DynamicFile>>,
     pub judge: hp_common::Judge,
     pub test: hp_common::Test,
+    pub diagnostics: bool,
     pub update_callback: UpdateCallbackSender,
     pub finish_callback: FinishCallbackSender,
 }
 
-type UpdateCallbackSender = async_channel::Sender<(Arc<str>, hp_common::JudgeState)>;
+struct TaskCallbacks {
+    /// the session which the task is dispatched to
+    ws_id: Arc<str>,
+    /// the identity of the judger, which is the only one to report the task
+    access_key: Arc<str>,
+    instance_id: Arc<str>,
+    /// the task fails if it is not finished before the deadline
+    deadline: time::Instant,
+    case_count: usize,
+    update: UpdateCallbackSender,
+    finish: FinishCallbackSender,
+}
+
+type UpdateCallbackSender = async_channel::Sender<(Arc<str>, UpdateJudgeCallback)>;
 type FinishCallbackSender = async_channel::Sender<(Arc<str>, hp_common::JudgeResult)>;
 
 impl JudgerModule {
     pub fn new() -> Self {
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
         Self {
             judger_map: RwLock::new(HashMap::new()),
             available_queue: Queue::unbounded(),
+            tasks: DashMap::new(),
+            parked_tasks: Mutex::new(Vec::new()),
+            shutdown_tx,
+            shutdown_rx,
+            local_dispatches: AtomicU64::new(0),
+            remote_dispatches: AtomicU64::new(0),
         }
     }
 
@@ -116,9 +176,12 @@ impl JudgerModule {
             ws_id: ws_id.clone(),
             info,
             state: RwLock::new(JudgerState::Registered { remove_task }),
-            rpc_timeout: config.judger.rpc_timeout,
-            // tasks: RwLock::new(HashMap::new()),
-            tasks: DashMap::new(),
+            rpc_config: RpcConfig {
+                timeout: Duration::from_millis(config.judger.rpc_timeout),
+                max_inflight: config.judger.rpc_max_inflight,
+            },
+            ws_sender: RwLock::new(None),
+            last_report: RwLock::new(None),
         });
 
         let mut judger_map: _ = self.judger_map.write().await;
@@ -132,41 +195,295 @@ impl JudgerModule {
     }
 
     pub async fn schedule(self: Arc<Self>, task: JudgeTask) -> Result<()> {
-        task::spawn(async move {
+        let mut shutdown_rx = self.shutdown_rx.clone();
+        let schedule = async move {
             let args = CreateJudgeArgs {
                 id: task.id.to_string(),
                 data: task.data.clone(),
                 dynamic_files: task.dynamic_files.clone(),
                 judge: task.judge.clone(),
                 test: task.test.clone(),
+                diagnostics: task.diagnostics,
             };
 
-            loop {
-                let judger = loop {
-                    let weak_judger = self.available_queue.pop().await;
-                    if let Some(judger) = weak_judger.upgrade() {
-                        break judger;
-                    }
-                };
+            // compares hashsums in the canonical form, such as `sha256:{hex}`
+            let hashsum = task
+                .data
+                .as_ref()
+                .and_then(|f| f.hashsum())
+                .and_then(Hashsum::parse)
+                .map(|h| h.to_string());
+
+            let task_timeout = Duration::from_millis(inject::<Config>().judger.task_timeout);
+
+            let deadline = loop {
+                let judger = self
+                    .acquire_supporting_judger(&task, hashsum.as_deref())
+                    .await;
 
-                judger.tasks.insert(
+                let deadline = time::Instant::now() + task_timeout;
+                self.tasks.insert(
                     task.id.clone(),
-                    (task.update_callback.clone(), task.finish_callback.clone()),
+                    TaskCallbacks {
+                        ws_id: judger.ws_id.clone(),
+                        access_key: judger.info.access_key.clone(),
+                        instance_id: judger.info.instance_id.clone(),
+                        deadline,
+                        case_count: task.test.cases.len(),
+                        update: task.update_callback.clone(),
+                        finish: task.finish_callback.clone(),
+                    },
                 );
 
                 if let Err(err) = judger.create_judge(args.clone()).await {
                     error!(?judger.ws_id, ?judger.info, %err, "failed to create judge");
-                    judger.tasks.remove(&task.id);
+                    self.tasks.remove(&task.id);
                     continue;
                 }
 
                 // info!(?judger.ws_id,?task.id, "create a judge task on the judger");
-                break;
+                break deadline;
+            };
+
+            // callbacks are kept by `self.tasks` until the task is finished
+            let id = task.id.clone();
+            drop(task);
+            time::sleep_until(deadline).await;
+            self.expire_task(&id).await;
+        };
+        task::spawn(async move {
+            // the task and its callbacks are dropped with the cancelled schedule
+            tokio::select! {
+                () = schedule => {}
+                _ = shutdown_rx.changed() => {}
             }
         });
         Ok(())
     }
 
+    /// fails a task whose judger has not reported the result before the deadline
+    ///
+    /// The finish callback is sent, so that stored files of the task are released.
+    async fn expire_task(&self, id: &str) {
+        let now = time::Instant::now();
+        let task = match self.tasks.remove_if(id, |_, task| task.deadline <= now) {
+            Some((_, task)) => task,
+            None => return,
+        };
+        warn!(?id, ws_id = ?task.ws_id, "the judger does not finish the task in time");
+
+        let case = hp_common::JudgeCaseResult {
+            kind: hp_common::JudgeResultKind::SystemError,
+            time: 0,
+            memory: 0,
+            score: None,
+            message: Some("the judger does not finish the task in time".to_owned()),
+            diagnostics: None,
+        };
+        let result = hp_common::JudgeResult {
+            cases: vec![case; task.case_count],
+            extra: None,
+            subtasks: None,
+            score: None,
+            discovered_cases: None,
+        };
+        let _ = task.finish.send((id.into(), result)).await;
+
+        // a late result is ignored, so the slot is given back now
+        if let Some(judger) = self.find_judger(&task.ws_id).await {
+            if judger.is_online().await {
+                self.release_slot(&judger).await;
+            }
+        }
+    }
+
+    async fn pop_judger(&self) -> Arc<Judger> {
+        loop {
+            let weak_judger = self.available_queue.pop().await;
+            if let Some(judger) = weak_judger.upgrade() {
+                return judger;
+            }
+        }
+    }
+
+    /// prefers a free judger which has cached the data
+    ///
+    /// Free judgers without the data are held until one with the data is free
+    /// or `locality_wait` elapses. Then the held ones are put back.
+    async fn acquire_judger(&self, hashsum: Option<&str>) -> Arc<Judger> {
+        let hashsum = match hashsum {
+            Some(h) => h,
+            None => return self.pop_judger().await,
+        };
+
+        if !self.any_has_data(hashsum).await {
+            self.remote_dispatches.fetch_add(1, Relaxed);
+            return self.pop_judger().await;
+        }
+
+        let locality_wait = inject::<Config>().judger.locality_wait;
+        let deadline = time::Instant::now() + Duration::from_millis(locality_wait);
+        let mut held = VecDeque::new();
+
+        let judger = loop {
+            match time::timeout_at(deadline, self.pop_judger()).await {
+                Ok(judger) if judger.has_data(hashsum).await => {
+                    self.local_dispatches.fetch_add(1, Relaxed);
+                    break judger;
+                }
+                Ok(judger) => held.push_back(judger),
+                Err(_) => {
+                    self.remote_dispatches.fetch_add(1, Relaxed);
+                    match held.pop_front() {
+                        Some(judger) => break judger,
+                        None => break self.pop_judger().await,
+                    }
+                }
+            }
+        };
+
+        for judger in held {
+            self.available_queue.push(Arc::downgrade(&judger)).await;
+        }
+        judger
+    }
+
+    /// acquires a judger which supports the languages of the task
+    ///
+    /// Each free slot is tried once. Then the task is parked until a slot is
+    /// released or a judger comes online, instead of polling the same slots.
+    async fn acquire_supporting_judger(
+        &self,
+        task: &JudgeTask,
+        hashsum: Option<&str>,
+    ) -> Arc<Judger> {
+        let mut warned = false;
+        loop {
+            let judger = self.acquire_judger(hashsum).await;
+            if judger.supports_languages(&task.judge) {
+                return judger;
+            }
+
+            // parks before giving back the slot, so that a slot released
+            // while trying the others is never missed
+            let (unpark_tx, unpark_rx) = oneshot::channel();
+            self.parked_tasks.lock().unwrap().push(unpark_tx);
+            self.available_queue.push(Arc::downgrade(&judger)).await;
+
+            for _ in 1..self.available_queue.len() {
+                let judger = self.acquire_judger(hashsum).await;
+                if judger.supports_languages(&task.judge) {
+                    return judger;
+                }
+                self.available_queue.push(Arc::downgrade(&judger)).await;
+            }
+
+            if !warned && !self.any_supports_languages(&task.judge).await {
+                warn!(?task.id, "no online judger supports the languages of the task");
+                warned = true;
+            }
+            let _ = unpark_rx.await;
+        }
+    }
+
+    /// gives back a slot of a judger and wakes parked tasks to try it
+    async fn release_slot(&self, judger: &Arc<Judger>) {
+        self.available_queue.push(Arc::downgrade(judger)).await;
+        self.unpark_tasks();
+    }
+
+    async fn any_has_data(&self, hashsum: &str) -> bool {
+        let judgers: Vec<Arc<Judger>> = self.judger_map.read().await.values().cloned().collect();
+        for judger in judgers {
+            if judger.has_data(hashsum).await {
+                return true;
+            }
+        }
+        false
+    }
+
+    async fn any_supports_languages(&self, judge: &hp_common::Judge) -> bool {
+        let judgers: Vec<Arc<Judger>> = self.judger_map.read().await.values().cloned().collect();
+        for judger in judgers {
+            if judger.supports_languages(judge) && judger.is_online().await {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// wakes all parked tasks to be scheduled again
+    fn unpark_tasks(&self) {
+        let parked_tasks = mem::take(&mut *self.parked_tasks.lock().unwrap());
+        for unpark_tx in parked_tasks {
+            let _ = unpark_tx.send(());
+        }
+    }
+
+    /// asks all online judgers to run the self-test again
+    ///
+    /// A judger logs in again to advertise its languages if they change.
+    pub async fn self_test(&self) -> usize {
+        let judgers: Vec<Arc<Judger>> = self.judger_map.read().await.values().cloned().collect();
+        let mut count = 0;
+        for judger in judgers {
+            let session = match judger.session().await {
+                Ok(session) => session,
+                Err(_) => continue,
+            };
+            let settings = PartialConnectionSettings {
+                status_report_interval: None,
+                self_test: true,
+            };
+            let req = RpcRequest::Control(Some(settings));
+            match session.request::<ConnectionSettings>(req).await {
+                Ok(_) => count += 1,
+                Err(err) => warn!(?judger.ws_id, %err, "failed to request self-test"),
+            }
+        }
+        count
+    }
+
+    pub async fn metrics(&self) -> Metrics {
+        let judgers: Vec<Arc<Judger>> = self.judger_map.read().await.values().cloned().collect();
+        let (mut hits, mut misses) = (0, 0);
+        for judger in judgers {
+            let last_report = judger.last_report.read().await;
+            let data = last_report
+                .as_ref()
+                .and_then(|r| r.report.as_ref())
+                .and_then(|r| r.data.as_ref());
+            if let Some(data) = data {
+                hits += data.hits;
+                misses += data.misses;
+            }
+        }
+        let total = hits + misses;
+        Metrics {
+            local_dispatches: self.local_dispatches.load(Relaxed),
+            remote_dispatches: self.remote_dispatches.load(Relaxed),
+            data_cache_hits: hits,
+            data_cache_misses: misses,
+            data_cache_hit_rate: if total > 0 {
+                Some(hits as f64 / total as f64)
+            } else {
+                None
+            },
+        }
+    }
+
+    /// closes all judger sessions and drops callbacks of unfinished tasks
+    ///
+    /// Unfinished tasks are still saved in redis.
+    pub async fn shutdown(&self) {
+        let _ = self.shutdown_tx.send(true);
+        let judgers: Vec<Arc<Judger>> = self.judger_map.read().await.values().cloned().collect();
+        for judger in judgers {
+            judger.close(1001, "controller is shutting down").await;
+        }
+        self.tasks.clear();
+    }
+
     pub(crate) async fn __test_schedule(self: Arc<Self>) {
         time::sleep(Duration::from_secs(5)).await;
         let tasks_count: usize = 10_0000;
@@ -199,7 +516,10 @@ impl JudgerModule {
                 test: hp_common::Test {
                     cases: Vec::new(),
                     policy: hp_common::TestPolicy::All,
+                    subtasks: None,
+                    discover: false,
                 },
+                diagnostics: false,
                 update_callback: update_tx.clone(),
                 finish_callback: finish_tx.clone(),
             };
@@ -221,6 +541,47 @@ impl JudgerModule {
 }
 
 impl Judger {
+    /// whether the judger is online and its last status report contains
+    /// the data hashsum in the canonical form
+    async fn has_data(&self, hashsum: &str) -> bool {
+        if !self.is_online().await {
+            return false;
+        }
+        let last_report = self.last_report.read().await;
+        let data = last_report
+            .as_ref()
+            .and_then(|r| r.report.as_ref())
+            .and_then(|r| r.data.as_ref());
+        match data {
+            Some(data) => data.hashsums.iter().any(|h| h == hashsum),
+            None => false,
+        }
+    }
+
+    /// whether the judger advertised every language of a judge
+    fn supports_languages(&self, judge: &hp_common::Judge) -> bool {
+        match self.info.languages {
+            None => true,
+            Some(ref languages) => judge
+                .executables()
+                .iter()
+                .all(|e| languages.contains(&e.environment.language)),
+        }
+    }
+
+    pub fn supports(&self, feature: Feature) -> bool {
+        self.info.features.contains(&feature)
+    }
+
+    fn codec(&self) -> Codec {
+        Codec::negotiate(&self.info.features)
+    }
+
+    async fn is_online(&self) -> bool {
+        let state = self.state.read().await;
+        matches!(*state, JudgerState::Online(_))
+    }
+
     pub async fn is_registered(&self) -> bool {
         let state = self.state.read().await;
         matches!(*state, JudgerState::Registered { .. })
@@ -229,26 +590,45 @@ impl Judger {
     pub async fn start_session(self: Arc<Self>, ws: WebSocket) {
         let (ws_sink, ws_stream) = ws.split();
 
-        let (tx, rx) = mpsc::channel::<ws::Message>(4096);
+        let (ws_tx, ws_rx) = mpsc::channel::<ws::Message>(4096);
         task::spawn(
-            ReceiverStream::new(rx)
+            ReceiverStream::new(ws_rx)
                 .map(Ok)
                 .forward(ws_sink)
                 .inspect_err(|err| error!(%err, "ws forward error")),
         );
 
-        let session = Arc::new(WsSession {
-            seq: AtomicU32::new(0),
-            callbacks: DashMap::new(),
-            sender: tx,
-        });
+        let (rpc_tx, rpc_rx) = mpsc::channel::<RpcMessage>(4096);
+        {
+            let ws_tx = ws_tx.clone();
+            let codec = self.codec();
+            task::spawn(async move {
+                let mut rpc_rx = ReceiverStream::new(rpc_rx);
+                while let Some(rpc_msg) = rpc_rx.next().await {
+                    let ws_msg = match codec.encode(&rpc_msg) {
+                        Ok(Frame::Text(text)) => ws::Message::text(text),
+                        Ok(Frame::Binary(bytes)) => ws::Message::binary(bytes),
+                        Err(err) => {
+                            error!(%err, "failed to encode rpc message");
+                            continue;
+                        }
+                    };
+                    if ws_tx.send(ws_msg).await.is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+
+        let session = Arc::new(RpcSession::new(rpc_tx, self.rpc_config.clone()));
+        *self.ws_sender.write().await = Some(ws_tx.clone());
 
         {
             let mut state = self.state.write().await;
             if !matches!(*state, JudgerState::Registered { .. }) {
                 warn!(ws_id = ?self.ws_id, "judger is already connected");
                 let close_msg = ws::Message::close_with(1011_u16, "judger is already connected");
-                let _ = session.sender.send(close_msg).await;
+                let _ = ws_tx.send(close_msg).await;
             }
 
             let prev_state = mem::replace(&mut *state, JudgerState::Online(session.clone()));
@@ -268,12 +648,22 @@ impl Judger {
             for _ in 0..max_task_count {
                 module.available_queue.push(weak_judger.clone()).await
             }
+            module.unpark_tasks();
         }
 
         task::spawn(self.run_session(session, ws_stream));
     }
 
-    async fn run_session(self: Arc<Self>, session: Arc<WsSession>, mut ws: SplitStream<WebSocket>) {
+    async fn run_session(
+        self: Arc<Self>,
+        session: Arc<RpcSession>,
+        mut ws: SplitStream<WebSocket>,
+    ) {
+        let handler = {
+            let this = self.clone();
+            move |req| this.clone().handle_rpc_request(req)
+        };
+
         while let Some(msg) = ws.next().await {
             let msg = match msg {
                 Ok(m) => m,
@@ -283,125 +673,154 @@ impl Judger {
                 }
             };
 
-            let text = match msg.to_str() {
-                Ok(t) => t,
-                Err(()) => {
-                    warn!("ignore non-text message");
-                    continue;
-                }
+            let result = if msg.is_text() {
+                codec::decode_text(msg.to_str().unwrap())
+            } else if msg.is_binary() {
+                codec::decode_binary(msg.as_bytes())
+            } else {
+                continue;
             };
 
-            let rpc_msg = match serde_json::from_str::<RpcMessage>(text) {
+            let rpc_msg = match result {
                 Ok(r) => r,
                 Err(err) => {
-                    error!(%err, ?text, "failed to parse ws text message");
+                    error!(%err, "failed to parse ws message");
                     continue;
                 }
             };
 
-            match rpc_msg {
-                RpcMessage::Request { seq, body, .. } => {
-                    let this = self.clone();
-                    let session = session.clone();
-                    task::spawn(async move {
-                        let response = this.handle_rpc_request(body).await;
-                        let rpc_msg = RpcMessage::Response {
-                            seq,
-                            time: Utc::now(),
-                            body: response,
-                        };
-                        let ws_msg = ws::Message::text(serde_json::to_string(&rpc_msg).unwrap());
-                        let _ = session.sender.send(ws_msg).await;
-                    });
-                }
-                RpcMessage::Response { seq, body, .. } => {
-                    let session = session.clone();
-                    match session.callbacks.remove(&seq) {
-                        None => warn!(?seq, "no such callback"),
-                        Some((_, cb)) => match cb.send(body) {
-                            Ok(()) => {}
-                            Err(_) => warn!(?seq, "the callback is timeouted"),
-                        },
-                    }
-                }
-            }
+            session.dispatch(rpc_msg, &handler);
         }
 
+        session.close();
         self.set_offline().await
     }
 
+    async fn close(&self, code: u16, reason: &'static str) {
+        let ws_sender = self.ws_sender.write().await.take();
+        if let Some(ws_tx) = ws_sender {
+            let _ = ws_tx.send(ws::Message::close_with(code, reason)).await;
+        }
+        let mut state = self.state.write().await;
+        match *state {
+            JudgerState::Online(ref session) | JudgerState::Disabled(ref session) => {
+                session.close()
+            }
+            _ => {}
+        }
+        *state = JudgerState::Offline;
+        *self.last_report.write().await = None;
+    }
+
     async fn set_offline(&self) {
         let mut state = self.state.write().await;
         *state = JudgerState::Offline;
+        // the cached data and the statistics are unknown after disconnecting
+        *self.last_report.write().await = None;
         // TODO: notify scheduler, re-dispatch all running tasks in the judger
     }
 
-    async fn wsrpc(&self, req: RpcRequest) -> Result<RpcResponse> {
-        let session = match *self.state.read().await {
-            JudgerState::Online(ref s) => Arc::clone(&s),
-            _ => return Err(format_err!("can not perform wsrpc on the judger")),
-        };
-
-        let seq = session.seq.fetch_add(1, Relaxed).wrapping_add(1);
-        let (tx, rx) = oneshot::channel();
-        let rpc_msg = RpcMessage::Request {
-            seq,
-            time: Utc::now(),
-            body: req,
-        };
-        let ws_msg = ws::Message::text(serde_json::to_string(&rpc_msg).unwrap());
-
-        session.callbacks.insert(seq, tx);
-        if session.sender.send(ws_msg).await.is_err() {
-            return Err(format_err!("judger has disconnected"));
-        }
-
-        match time::timeout(Duration::from_millis(self.rpc_timeout), rx).await {
-            Ok(res) => Ok(res.unwrap()),
-            Err(err) => {
-                let _ = session.callbacks.remove(&seq);
-                return Err(anyhow::Error::new(err));
-            }
+    async fn session(&self) -> Result<Arc<RpcSession>> {
+        match *self.state.read().await {
+            JudgerState::Online(ref s) => Ok(Arc::clone(s)),
+            _ => Err(format_err!("can not perform wsrpc on the judger")),
         }
     }
 
     // judger => controller
     async fn handle_rpc_request(self: Arc<Self>, req: RpcRequest) -> RpcResponse {
         match req {
-            RpcRequest::ReportStatus(status) => {
-                // dbg!(status);
-                RpcResponse::Output(None)
+            RpcRequest::ReportStatus(mut status) => {
+                if !self.supports(Feature::StatusReport) {
+                    status.report = None;
+                }
+                debug!(ws_id = ?self.ws_id, report = ?status.report, "report status");
+                *self.last_report.write().await = Some(status);
+                RpcResponse::null()
             }
-            RpcRequest::UpdateJudge(update) => {
-                // dbg!(update);
-                RpcResponse::Output(None)
+            RpcRequest::UpdateJudge(mut update) => {
+                if !self.supports(Feature::JudgeProgress) {
+                    update.current_case = None;
+                    update.cases = None;
+                }
+                let module = self.module.upgrade().unwrap();
+                let task = module.tasks.get(&*update.id).map(|entry| {
+                    (
+                        entry.key().clone(),
+                        self.owns(&entry),
+                        entry.update.clone(),
+                    )
+                });
+                if let Some((id, is_owner, update_tx)) = task {
+                    if !is_owner {
+                        return self.reject_foreign_task(&id);
+                    }
+                    let callback = UpdateJudgeCallback {
+                        state: update.state.into(),
+                        current_case: update.current_case,
+                        cases: update.cases,
+                    };
+                    // never waits for the outbox, which may be retrying a callback,
+                    // and the next update carries all cases so far
+                    let _ = update_tx.try_send((id, callback));
+                }
+                RpcResponse::null()
             }
             RpcRequest::FinishJudge(finish) => {
                 let module = self.module.upgrade().unwrap();
-                // let mut tasks = self.tasks.write().await;
-                if let Some((id, (_, finish_tx))) = self.tasks.remove(&*finish.id) {
-                    let _ = finish_tx.send((id, finish.result)).await;
-                    module.available_queue.push(Arc::downgrade(&self)).await;
+                // a result may be resent after reconnecting, so it is ignored if unknown
+                let is_foreign = module
+                    .tasks
+                    .get(&*finish.id)
+                    .map_or(false, |task| !self.owns(&task));
+                if is_foreign {
+                    return self.reject_foreign_task(&finish.id);
+                }
+                let task = module
+                    .tasks
+                    .remove_if(&*finish.id, |_, task| self.owns(task));
+                if let Some((id, task)) = task {
+                    let _ = task.finish.send((id, finish.result)).await;
+                    // the slot of a previous session is not reused,
+                    // since the new session has offered all of its slots
+                    if task.ws_id == self.ws_id && self.is_online().await {
+                        module.release_slot(&self).await;
+                    }
                 }
-                RpcResponse::Output(None)
+                RpcResponse::null()
             }
-            _ => RpcResponse::Error(ErrorInfo {
-                code: ErrorCode::NotSupported,
-                message: None,
-            }),
+            RpcRequest::Drain => {
+                if !self.supports(Feature::Drain) {
+                    return RpcResponse::error(ErrorCode::NotSupported, None);
+                }
+                let mut state = self.state.write().await;
+                if let JudgerState::Online(ref session) = *state {
+                    info!(ws_id = ?self.ws_id, "judger is draining");
+                    *state = JudgerState::Disabled(session.clone());
+                }
+                RpcResponse::null()
+            }
+            _ => RpcResponse::error(ErrorCode::NotSupported, None),
         }
     }
 
-    pub async fn create_judge(&self, args: CreateJudgeArgs) -> Result<()> {
-        let res = self.wsrpc(RpcRequest::CreateJudge(args)).await?;
-        match res {
-            RpcResponse::Output(output) => {
-                if output.is_some() {
-                    warn!("expected null response");
-                }
-            }
-            RpcResponse::Error(err) => return Err(anyhow::Error::new(err)),
+    /// whether the task is dispatched to this judger, in this session or a previous one
+    fn owns(&self, task: &TaskCallbacks) -> bool {
+        task.access_key == self.info.access_key && task.instance_id == self.info.instance_id
+    }
+
+    fn reject_foreign_task(&self, id: &str) -> RpcResponse {
+        warn!(ws_id = ?self.ws_id, ?id, "reject a task which is not dispatched to the judger");
+        let message = format!("task is not dispatched to the judger: id = {}", id);
+        RpcResponse::error(ErrorCode::PermissionDenied, Some(message))
+    }
+
+    pub async fn create_judge(&self, mut args: CreateJudgeArgs) -> Result<()> {
+        if self.codec() == Codec::MessagePack {
+            args = args.into_binary_files();
         }
+        let session = self.session().await?;
+        session.request::<()>(RpcRequest::CreateJudge(args)).await?;
         Ok(())
     }
 
@@ -4,6 +4,7 @@ mod auth;
 mod config;
 mod errors;
 mod external;
+mod files;
 mod judger;
 mod redis;
 mod routes;
@@ -11,23 +12,28 @@ mod routes;
 use self::auth::AuthModule;
 pub use self::config::Config;
 use self::external::ExternalModule;
+use self::files::FileModule;
 use self::judger::JudgerModule;
 use self::redis::RedisModule;
 
 use heng_utils::container::{inject, Container};
+use heng_utils::signal;
 
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 
 pub use anyhow::Result;
+use tracing::info;
 
 pub fn init(config: Config) -> Result<()> {
     let config = Arc::new(config);
 
     let redis_module = Arc::new(RedisModule::new(&config)?);
     let judger_module = Arc::new(JudgerModule::new());
-    let external_module = Arc::new(ExternalModule::new(redis_module.clone()));
+    let external_module = Arc::new(ExternalModule::new(&config, redis_module.clone())?);
     let auth_module = Arc::new(AuthModule::new(&config, redis_module.clone()));
+    let file_module = Arc::new(FileModule::new(&config)?);
 
     let mut container = Container::new();
 
@@ -36,6 +42,7 @@ pub fn init(config: Config) -> Result<()> {
     container.register(judger_module);
     container.register(external_module);
     container.register(auth_module);
+    container.register(file_module);
 
     container.install_global();
     Ok(())
@@ -46,10 +53,26 @@ pub async fn run() -> Result<()> {
         let module = inject::<JudgerModule>();
         tokio::task::spawn(module.__test_schedule());
     }
+    {
+        let module = inject::<FileModule>();
+        tokio::task::spawn(module.gc_loop());
+    }
 
     let config: Arc<Config> = inject();
     let addr = config.server.address.parse::<SocketAddr>()?;
-    let server = warp::serve(routes::routes());
-    server.bind(addr).await;
+    let (_, server) =
+        warp::serve(routes::routes()).try_bind_with_graceful_shutdown(addr, signal::shutdown())?;
+    server.await;
+    info!("shutting down");
+
+    // cancelling schedules and closing sessions drop the senders of callbacks,
+    // so that outboxes can be flushed
+    inject::<JudgerModule>().shutdown().await;
+    let shutdown_timeout = Duration::from_millis(config.server.shutdown_timeout);
+    inject::<ExternalModule>()
+        .flush_outboxes(shutdown_timeout)
+        .await;
+
+    info!("controller is stopped");
     Ok(())
 }
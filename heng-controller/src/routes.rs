@@ -1,14 +1,19 @@
 use crate::auth::{self, AuthModule, ClientKind};
 use crate::errors::{self, reject_anyhow, reject_error};
 use crate::external::ExternalModule;
-use crate::judger::{JudgeTask, JudgerInfo, JudgerModule};
+use crate::files::{self, FileModule};
+use crate::judger::{self, JudgeTask, JudgerInfo, JudgerModule};
 
 use heng_utils::container::inject;
+use heng_utils::crypto::{hex_sha256, HashAlgorithm, Hasher};
 
 use heng_protocol::error::ErrorCode;
-use heng_protocol::external::{CallbackUrls, CreateJudgeRequest};
+use heng_protocol::external::{CallbackUrls, CreateJudgeRequest, FinishJudgeCallback};
 use heng_protocol::internal::http::{AcquireTokenOutput, AcquireTokenRequest};
-use heng_protocol::signature::calc_signature;
+use heng_protocol::internal::{
+    is_compatible_version, negotiate_features, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION,
+};
+use heng_protocol::signature::calc_signature_with_body_hash;
 use serde::de::DeserializeOwned;
 use serde_json::from_slice;
 use warp::http::HeaderValue;
@@ -16,15 +21,19 @@ use warp::hyper::{HeaderMap, Method};
 use warp::path::FullPath;
 
 use std::convert::Infallible;
+use std::io::SeekFrom;
 use std::sync::Arc;
 
 use anyhow::Result;
-use bytes::Bytes;
-use serde::Deserialize;
-use tokio::task;
+use bytes::{Buf, Bytes};
+use futures::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
 use uuid::Uuid;
 use validator::Validate;
 use warp::filters::ws;
+use warp::http::{header, StatusCode};
+use warp::hyper::Body;
 use warp::reply::{self, Response};
 use warp::{Filter, Rejection, Reply};
 
@@ -47,7 +56,7 @@ macro_rules! reject {
 pub fn routes() -> impl_filter!(impl Reply,) {
     let prefix: _ = warp::path("v1");
 
-    let routes: _ = judgers_routes().or(judges_routes());
+    let routes: _ = judgers_routes().or(judges_routes()).or(files_routes());
 
     prefix.and(routes).recover(errors::recover)
 }
@@ -68,7 +77,17 @@ fn judgers_routes() -> impl_filter!(impl Reply,) {
         .and(warp::ws())
         .and_then(|(c, b), q, ws| async move { websocket(c, q, ws).await });
 
-    let routes: _ = acquire_token.or(websocket);
+    let metrics: _ = warp::path("metrics")
+        .and(warp::get())
+        .and(signature_guard())
+        .and_then(|(c, _)| async move { metrics(c).await });
+
+    let self_test: _ = warp::path("self-test")
+        .and(warp::post())
+        .and(signature_guard())
+        .and_then(|(c, _)| async move { self_test(c).await });
+
+    let routes: _ = acquire_token.or(websocket).or(metrics).or(self_test);
     prefix.and(routes)
 }
 
@@ -82,6 +101,28 @@ fn judges_routes() -> impl_filter!(impl Reply,) {
     prefix.and(create_judge)
 }
 
+fn files_routes() -> impl_filter!(impl Reply,) {
+    let prefix: _ = warp::path("files");
+    let max_size = inject::<FileModule>().max_size();
+
+    let put_file: _ = warp::path::param::<String>()
+        .and(warp::path::end())
+        .and(warp::put())
+        .and(signed_request())
+        .and(warp::body::content_length_limit(max_size))
+        .and(warp::body::stream())
+        .and_then(|h, r, b| async move { put_file(h, r, b).await });
+
+    let get_file: _ = warp::path::param::<String>()
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(signature_guard())
+        .and(warp::header::optional::<String>("range"))
+        .and_then(|h, (c, _), r| async move { get_file(c, h, r).await });
+
+    prefix.and(put_file.or(get_file))
+}
+
 fn query_optional() -> impl_filter!(Option<String>,) {
     warp::query::raw()
         .map(Some)
@@ -95,28 +136,50 @@ fn json<T: DeserializeOwned>(body: Bytes) -> Result<T, Rejection> {
     }
 }
 
-fn signature_guard() -> impl_filter!((auth::Client, Bytes),) {
+/// the signed parts of a request, except the body
+struct SignedRequest {
+    access_key: HeaderValue,
+    signature: HeaderValue,
+    method: Method,
+    path: FullPath,
+    query: Option<String>,
+    headers: HeaderMap,
+}
+
+fn signed_request() -> impl_filter!(SignedRequest,) {
     warp::header::value("x-heng-accesskey")
         .and(warp::header::value("x-heng-signature"))
         .and(warp::method())
         .and(warp::path::full())
         .and(query_optional())
         .and(warp::header::headers_cloned())
+        .map(
+            |access_key, signature, method, path, query, headers| SignedRequest {
+                access_key,
+                signature,
+                method,
+                path,
+                query,
+                headers,
+            },
+        )
+}
+
+fn signature_guard() -> impl_filter!((auth::Client, Bytes),) {
+    signed_request()
         .and(warp::body::content_length_limit(BODY_SIZE_HARD_LIMIT))
         .and(warp::body::bytes())
-        .and_then(check_signature)
+        .and_then(|req, body: Bytes| async move {
+            let (client, secret_key) = lookup_client(&req)?;
+            check_signature(&req, &secret_key, &hex_sha256(&body))?;
+            Ok::<_, Rejection>((client, body))
+        })
 }
 
-async fn check_signature(
-    access_key: HeaderValue,
-    signature: HeaderValue,
-    method: Method,
-    path: FullPath,
-    query: Option<String>,
-    headers: HeaderMap,
-    body: Bytes,
-) -> Result<(auth::Client, Bytes), Rejection> {
-    let access_key = access_key
+/// finds the client of the access key, and its secret key
+fn lookup_client(req: &SignedRequest) -> Result<(auth::Client, Box<str>), Rejection> {
+    let access_key = req
+        .access_key
         .to_str()
         .map_err(|err| reject_anyhow(err.into()))?;
 
@@ -127,31 +190,28 @@ async fn check_signature(
         None => reject!(ErrorCode::SignatureMismatch),
     };
 
-    let expected_signature = calc_signature(
-        &method,
-        path.as_str(),
-        query.as_deref().unwrap_or(""),
-        &headers,
-        &*body,
-        &secret_key,
+    let client = auth::Client {
+        kind: client_kind,
+        access_key: access_key.into(),
+    };
+    Ok((client, secret_key))
+}
+
+/// checks the signature with the sha256 hex of the body
+fn check_signature(req: &SignedRequest, secret_key: &str, body_hash: &str) -> Result<(), Rejection> {
+    let expected_signature = calc_signature_with_body_hash(
+        &req.method,
+        req.path.as_str(),
+        req.query.as_deref().unwrap_or(""),
+        &req.headers,
+        body_hash,
+        secret_key,
     );
 
-    if expected_signature.as_bytes() != signature.as_bytes() {
+    if expected_signature.as_bytes() != req.signature.as_bytes() {
         reject!(ErrorCode::SignatureMismatch)
     }
-
-    // let body = match serde_json::from_slice::<T>(&body) {
-    //     Ok(x) => x,
-    //     Err(err) => reject!(ErrorCode::InvalidRequest, err.to_string()),
-    // };
-
-    Ok((
-        auth::Client {
-            kind: client_kind,
-            access_key: access_key.into(),
-        },
-        body,
-    ))
+    Ok(())
 }
 
 /// POST /v1/judgers/token
@@ -164,13 +224,30 @@ async fn acquire_token(
         reject!(ErrorCode::InvalidRequest)
     }
 
+    if !is_compatible_version(body.protocol_version) {
+        reject!(
+            ErrorCode::IncompatibleProtocol,
+            format!(
+                "unsupported protocol version {}, expected {}..={}",
+                body.protocol_version, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION
+            )
+        )
+    }
+
     let judger_module = inject::<JudgerModule>();
 
+    let features = negotiate_features(judger::FEATURES, &body.features);
+
     let info = JudgerInfo {
+        access_key: client.access_key.into(),
+        instance_id: body.instance_id.into(),
         max_task_count: body.max_task_count,
         name: body.name,
         core_count: body.core_count,
         system_info: body.software,
+        protocol_version: body.protocol_version,
+        features: features.clone(),
+        languages: body.languages,
     };
 
     let ws_id = judger_module
@@ -180,6 +257,8 @@ async fn acquire_token(
 
     let output = AcquireTokenOutput {
         token: ws_id.to_string(),
+        protocol_version: PROTOCOL_VERSION,
+        features,
     };
 
     Ok(reply::json(&output).into_response())
@@ -211,6 +290,118 @@ async fn websocket(
     Ok(ws.on_upgrade(move |ws| judger.start_session(ws)))
 }
 
+/// GET /v1/judgers/metrics
+/// JSON: () => Metrics
+async fn metrics(_client: auth::Client) -> Result<Response, Rejection> {
+    let judger_module = inject::<JudgerModule>();
+    let metrics = judger_module.metrics().await;
+    Ok(reply::json(&metrics).into_response())
+}
+
+/// POST /v1/judgers/self-test
+/// JSON: () => SelfTestOutput
+///
+/// asks all online judgers to run the self-test again
+async fn self_test(_client: auth::Client) -> Result<Response, Rejection> {
+    let judger_module = inject::<JudgerModule>();
+    let judgers = judger_module.self_test().await;
+    Ok(reply::json(&SelfTestOutput { judgers }).into_response())
+}
+
+#[derive(Debug, Serialize)]
+struct SelfTestOutput {
+    /// the number of judgers which accept the request
+    judgers: usize,
+}
+
+/// PUT /v1/files/{hashsum}
+///
+/// `hashsum` is `{algorithm}:{hex}`, `{algorithm}-{hex}` or bare sha256 hex
+/// BINARY => ()
+///
+/// The body is written to disk while it is hashed, and the file is only stored
+/// if both the signature and the hashsum match.
+async fn put_file(
+    hashsum: String,
+    req: SignedRequest,
+    body: impl Stream<Item = Result<impl Buf, warp::Error>>,
+) -> Result<Response, Rejection> {
+    let (_client, secret_key) = lookup_client(&req)?;
+
+    let file_module = inject::<FileModule>();
+    let mut upload = file_module.upload(&hashsum).await.map_err(reject_anyhow)?;
+    let mut body_hasher = Hasher::new(HashAlgorithm::Sha256);
+
+    futures::pin_mut!(body);
+    while let Some(chunk) = body.next().await {
+        let mut chunk = chunk.map_err(|err| reject_anyhow(err.into()))?;
+        let chunk = chunk.copy_to_bytes(chunk.remaining());
+        body_hasher.update(&chunk);
+        upload.write(&chunk).await.map_err(reject_anyhow)?;
+    }
+
+    check_signature(&req, &secret_key, &body_hasher.finalize_hex())?;
+    upload.commit().await.map_err(reject_anyhow)?;
+    Ok(reply::reply().into_response())
+}
+
+/// GET /v1/files/{hashsum}
+/// () => BINARY
+///
+/// supports `Range: bytes={start}-` for resuming downloads
+async fn get_file(
+    _client: auth::Client,
+    hashsum: String,
+    range: Option<String>,
+) -> Result<Response, Rejection> {
+    let file_module = inject::<FileModule>();
+
+    let path = match file_module.get(&hashsum) {
+        Some(p) => p,
+        None => reject!(ErrorCode::NotFound),
+    };
+
+    let mut file = tokio::fs::File::open(&path)
+        .await
+        .map_err(|err| reject_anyhow(err.into()))?;
+    let len = file
+        .metadata()
+        .await
+        .map_err(|err| reject_anyhow(err.into()))?
+        .len();
+
+    let start = files::range_start(range.as_deref(), len);
+
+    let mut res = warp::http::Response::builder();
+    if let Some(start) = start {
+        file.seek(SeekFrom::Start(start))
+            .await
+            .map_err(|err| reject_anyhow(err.into()))?;
+        res = res.status(StatusCode::PARTIAL_CONTENT).header(
+            header::CONTENT_RANGE,
+            format!("bytes {}-{}/{}", start, len - 1, len),
+        );
+    }
+    let content_length = len - start.unwrap_or(0);
+
+    let chunks = futures::stream::unfold(file, |mut file| async move {
+        let mut buf = vec![0; 64 * 1024];
+        match file.read(&mut buf).await {
+            Ok(0) => None,
+            Ok(n) => {
+                buf.truncate(n);
+                Some((Ok(Bytes::from(buf)), file))
+            }
+            Err(err) => Some((Err(err), file)),
+        }
+    });
+
+    res.header(header::CONTENT_LENGTH, content_length)
+        .header(header::CONTENT_TYPE, "application/octet-stream")
+        .body(Body::wrap_stream(chunks))
+        .map_err(|err| reject_anyhow(err.into()))
+}
+
 /// POST /v1/judges
 /// JSON: CreateJudgeRequest => ()
 async fn create_judge(
@@ -220,12 +411,23 @@ async fn create_judge(
     let judger_module = inject::<JudgerModule>();
     let external_module = inject::<ExternalModule>();
 
+    let file_module = inject::<FileModule>();
+
     let task_id: Arc<str> = Uuid::new_v4().to_string().into();
 
-    external_module
-        .save_judge(&*task_id, &body)
-        .await
-        .map_err(reject_anyhow)?;
+    // stored files are kept until the judge is finished
+    let stored_files = files::stored_files(
+        body.data.as_ref(),
+        body.dynamic_files.as_deref(),
+        &body.judge,
+    )
+    .map_err(reject_anyhow)?;
+    file_module.retain(&stored_files).map_err(reject_anyhow)?;
+
+    if let Err(err) = external_module.save_judge(&*task_id, &body).await {
+        file_module.release(&stored_files);
+        return Err(reject_anyhow(err));
+    }
 
     let CreateJudgeRequest {
         data,
@@ -233,6 +435,7 @@ async fn create_judge(
         judge,
         test,
         callback_urls,
+        diagnostics,
     } = body;
 
     let CallbackUrls {
@@ -241,21 +444,34 @@ async fn create_judge(
     } = callback_urls;
 
     let update_callback = {
-        let (tx, rx) = async_channel::bounded(0);
-        task::spawn(async move {
-            while let Ok((task_id, state)) = rx.recv().await {
-                dbg!((&update_url, &task_id, &state));
+        let (tx, rx) = async_channel::bounded::<(Arc<str>, _)>(16);
+        let external = external_module.clone();
+        external_module.spawn_outbox(async move {
+            while let Ok(mut latest) = rx.recv().await {
+                // updates queued while a callback is retried are stale
+                while let Ok(update) = rx.try_recv() {
+                    latest = update;
+                }
+                let (task_id, update) = latest;
+                external
+                    .post_callback(&update_url, &*task_id, &update)
+                    .await;
             }
         });
         tx
     };
 
     let finish_callback = {
-        let (tx, rx) = async_channel::bounded::<(Arc<str>, _)>(0);
-        task::spawn(async move {
+        let (tx, rx) = async_channel::bounded::<(Arc<str>, _)>(1);
+        let external = external_module.clone();
+        external_module.spawn_outbox(async move {
             if let Ok((task_id, result)) = rx.recv().await {
-                let _ = external_module.remove_judge(&*task_id).await;
-                dbg!(&finish_url, &task_id, &result);
+                file_module.release(&stored_files);
+                let _ = external.remove_judge(&*task_id).await;
+                let callback = FinishJudgeCallback { result };
+                external
+                    .post_callback(&finish_url, &*task_id, &callback)
+                    .await;
             }
         });
         tx
@@ -267,6 +483,7 @@ async fn create_judge(
         dynamic_files,
         judge,
         test,
+        diagnostics,
         update_callback,
         finish_callback,
     };
@@ -0,0 +1,425 @@
+use std::fs;
+use std::io::{self, BufReader, Read, Seek, Write};
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{Context, Result};
+use zip::ZipArchive;
+
+const BUFFER_SIZE: usize = 4 * 1024 * 1024;
+
+/// limits of extracting an untrusted archive
+#[derive(Debug, Clone)]
+pub struct ExtractLimit {
+    /// total size of extracted files
+    pub max_uncompressed_size: u64,
+    /// number of files and directories
+    pub max_entries: u64,
+    /// total size of extracted files divided by the archive size
+    pub max_ratio: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Zip,
+    Tar,
+    TarGz,
+    TarZst,
+}
+
+impl ArchiveFormat {
+    /// detects the format by magic bytes
+    pub fn detect(header: &[u8]) -> Option<Self> {
+        const ZIP: &[&[u8]] = &[b"PK\x03\x04", b"PK\x05\x06"];
+        const GZIP: &[u8] = b"\x1f\x8b";
+        const ZSTD: &[u8] = b"\x28\xb5\x2f\xfd";
+        const USTAR_OFFSET: usize = 257;
+
+        if ZIP.iter().any(|m| header.starts_with(m)) {
+            return Some(Self::Zip);
+        }
+        if header.starts_with(GZIP) {
+            return Some(Self::TarGz);
+        }
+        if header.starts_with(ZSTD) {
+            return Some(Self::TarZst);
+        }
+        match header.get(USTAR_OFFSET..USTAR_OFFSET + 5) {
+            Some(b"ustar") => Some(Self::Tar),
+            _ => None,
+        }
+    }
+}
+
+/// extracts a zip, tar, tar.gz or tar.zst archive into `target_dir`
+///
+/// Entries escaping `target_dir` and links are rejected.
+/// The extraction fails as soon as any limit is exceeded.
+pub fn extract(file_path: &Path, target_dir: &Path, limit: &ExtractLimit) -> Result<()> {
+    let mut header = [0; 512];
+    let header_len = {
+        let mut file = fs::File::open(file_path)?;
+        read_full(&mut file, &mut header)?
+    };
+    let format = ArchiveFormat::detect(&header[..header_len])
+        .ok_or_else(|| anyhow::format_err!("unknown archive format"))?;
+
+    let file = fs::File::open(file_path)?;
+    let mut budget = Budget {
+        limit,
+        archive_size: file.metadata()?.len(),
+        entries: 0,
+        size: 0,
+    };
+    let reader = BufReader::with_capacity(BUFFER_SIZE, file);
+
+    fs::create_dir_all(target_dir)?;
+    match format {
+        ArchiveFormat::Zip => unzip(reader, target_dir, &mut budget)?,
+        ArchiveFormat::Tar => untar(reader, target_dir, &mut budget)?,
+        ArchiveFormat::TarGz => {
+            let decoder = flate2::read::GzDecoder::new(reader);
+            untar(decoder, target_dir, &mut budget)?
+        }
+        ArchiveFormat::TarZst => {
+            let decoder = zstd::Decoder::with_buffer(reader)?;
+            untar(decoder, target_dir, &mut budget)?
+        }
+    }
+    Ok(())
+}
+
+/// tracks the extracted entries and bytes against the limit
+struct Budget<'a> {
+    limit: &'a ExtractLimit,
+    archive_size: u64,
+    entries: u64,
+    size: u64,
+}
+
+impl Budget<'_> {
+    fn add_entry(&mut self) -> Result<()> {
+        self.entries += 1;
+        if self.entries > self.limit.max_entries {
+            anyhow::bail!(
+                "too many entries in archive: max_entries = {}",
+                self.limit.max_entries
+            );
+        }
+        Ok(())
+    }
+
+    fn add_size(&mut self, len: u64) -> Result<()> {
+        self.size = self.size.saturating_add(len);
+        if self.size > self.limit.max_uncompressed_size {
+            anyhow::bail!(
+                "archive is too large when uncompressed: max_uncompressed_size = {}",
+                self.limit.max_uncompressed_size
+            );
+        }
+        let max_size = self.archive_size.saturating_mul(self.limit.max_ratio);
+        if self.size > max_size {
+            anyhow::bail!(
+                "archive compression ratio is too high: archive_size = {}, max_ratio = {}",
+                self.archive_size,
+                self.limit.max_ratio
+            );
+        }
+        Ok(())
+    }
+
+    /// copies a file, which never trusts the declared size
+    fn copy(&mut self, reader: &mut impl Read, target: &Path) -> Result<()> {
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = fs::File::create(target)?;
+        let mut buf = vec![0; 64 * 1024];
+        loop {
+            let n = match reader.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err.into()),
+            };
+            self.add_size(n as u64)?;
+            file.write_all(&buf[..n])?;
+        }
+        Ok(())
+    }
+}
+
+fn unzip(reader: impl Read + Seek, target_dir: &Path, budget: &mut Budget) -> Result<()> {
+    let mut zip = ZipArchive::new(reader)?;
+    for i in 0..zip.len() {
+        budget.add_entry()?;
+        let mut entry = zip.by_index(i)?;
+        let name = entry.name().to_owned();
+        if name.contains('\0') {
+            anyhow::bail!("invalid entry path: {:?}", name);
+        }
+        let target = enclosed_path(target_dir, Path::new(&name))
+            .with_context(|| format!("invalid entry path: {}", name))?;
+
+        const S_IFMT: u32 = 0o170000;
+        const S_IFLNK: u32 = 0o120000;
+        if entry.unix_mode().map_or(false, |m| m & S_IFMT == S_IFLNK) {
+            anyhow::bail!("links are not allowed: {}", name);
+        }
+
+        if entry.is_dir() {
+            fs::create_dir_all(&target)?;
+        } else {
+            budget.copy(&mut entry, &target)?;
+        }
+    }
+    Ok(())
+}
+
+fn untar(reader: impl Read, target_dir: &Path, budget: &mut Budget) -> Result<()> {
+    let mut archive = tar::Archive::new(reader);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let entry_type = entry.header().entry_type();
+        if entry_type.is_pax_global_extensions() || entry_type.is_pax_local_extensions() {
+            continue;
+        }
+        budget.add_entry()?;
+
+        let path = entry.path()?.into_owned();
+        let target = enclosed_path(target_dir, &path)
+            .with_context(|| format!("invalid entry path: {}", path.display()))?;
+
+        if entry_type.is_dir() {
+            fs::create_dir_all(&target)?;
+        } else if entry_type.is_file() {
+            budget.copy(&mut entry, &target)?;
+        } else if entry_type.is_symlink() || entry_type.is_hard_link() {
+            anyhow::bail!("links are not allowed: {}", path.display());
+        } else {
+            anyhow::bail!("unsupported entry type: {}", path.display());
+        }
+    }
+    Ok(())
+}
+
+/// joins a relative path without parent components
+fn enclosed_path(target_dir: &Path, path: &Path) -> Result<PathBuf> {
+    let mut ans = target_dir.to_owned();
+    for component in path.components() {
+        match component {
+            Component::Normal(c) => ans.push(c),
+            Component::CurDir => {}
+            _ => anyhow::bail!("path is not enclosed"),
+        }
+    }
+    Ok(ans)
+}
+
+fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut len = 0;
+    while len < buf.len() {
+        match reader.read(&mut buf[len..])? {
+            0 => break,
+            n => len += n,
+        }
+    }
+    Ok(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use heng_utils::temp_dir::TempDir;
+
+    const LIMIT: ExtractLimit = ExtractLimit {
+        max_uncompressed_size: 1024 * 1024,
+        max_entries: 16,
+        max_ratio: 100,
+    };
+
+    /// writes the archive into the directory and extracts it into `data`
+    fn extract_in(dir: &TempDir, archive: &[u8], limit: &ExtractLimit) -> Result<PathBuf> {
+        let archive_path = dir.join("archive");
+        let target_dir = dir.join("data");
+        let _ = fs::remove_dir_all(&target_dir);
+        fs::write(&archive_path, archive).unwrap();
+        extract(&archive_path, &target_dir, limit).map(|()| target_dir)
+    }
+
+    fn tar_header(entry_type: tar::EntryType, size: u64) -> tar::Header {
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(entry_type);
+        header.set_size(size);
+        header.set_mode(0o644);
+        header
+    }
+
+    fn build_tar(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for &(path, content) in files {
+            let mut header = tar_header(tar::EntryType::Regular, content.len() as u64);
+            builder.append_data(&mut header, path, content).unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    /// builds a tar with a raw entry name, which bypasses the path checks of the builder
+    fn build_raw_tar(name: &str, entry_type: tar::EntryType, link: Option<&str>) -> Vec<u8> {
+        let mut header = tar_header(entry_type, 0);
+        header.as_old_mut().name[..name.len()].copy_from_slice(name.as_bytes());
+        if let Some(link) = link {
+            header.set_link_name(link).unwrap();
+        }
+        header.set_cksum();
+        let mut builder = tar::Builder::new(Vec::new());
+        builder.append(&header, io::empty()).unwrap();
+        builder.into_inner().unwrap()
+    }
+
+    fn build_zip(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(io::Cursor::new(Vec::new()));
+        for &(path, content) in files {
+            let options = zip::write::FileOptions::default();
+            writer.start_file(path, options).unwrap();
+            writer.write_all(content).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    fn assert_error(result: Result<PathBuf>, message: &str) {
+        let err = result.unwrap_err();
+        let text = format!("{:#}", err);
+        assert!(text.contains(message), "error = {}", text);
+    }
+
+    #[test]
+    fn detect() {
+        assert_eq!(
+            ArchiveFormat::detect(b"PK\x03\x04rest"),
+            Some(ArchiveFormat::Zip)
+        );
+        assert_eq!(
+            ArchiveFormat::detect(b"\x1f\x8b\x08"),
+            Some(ArchiveFormat::TarGz)
+        );
+        assert_eq!(
+            ArchiveFormat::detect(b"\x28\xb5\x2f\xfd\x00"),
+            Some(ArchiveFormat::TarZst)
+        );
+
+        let mut tar = vec![0; 512];
+        tar[257..263].copy_from_slice(b"ustar\0");
+        assert_eq!(ArchiveFormat::detect(&tar), Some(ArchiveFormat::Tar));
+
+        assert_eq!(ArchiveFormat::detect(b"hello"), None);
+    }
+
+    #[test]
+    fn extract_formats() {
+        let tar = build_tar(&[("tests/1.in", b"1 2")]);
+        let gz = {
+            let mut encoder =
+                flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(&tar).unwrap();
+            encoder.finish().unwrap()
+        };
+        let zst = zstd::encode_all(&*tar, 0).unwrap();
+        let zip = build_zip(&[("tests/1.in", b"1 2")]);
+
+        let dir = TempDir::new("heng-archive-formats").unwrap();
+        for (name, archive) in &[("tar", tar), ("tar.gz", gz), ("tar.zst", zst), ("zip", zip)] {
+            let data_dir = extract_in(&dir, archive, &LIMIT).unwrap();
+            let input = fs::read(data_dir.join("tests/1.in")).unwrap();
+            assert_eq!(input, b"1 2", "format = {}", name);
+        }
+    }
+
+    #[test]
+    fn reject_escaping_paths() {
+        let dir = TempDir::new("heng-archive-paths").unwrap();
+
+        let zip = build_zip(&[("../evil", b"x")]);
+        assert_error(extract_in(&dir, &zip, &LIMIT), "invalid entry path");
+
+        let zip = build_zip(&[("/etc/evil", b"x")]);
+        assert_error(extract_in(&dir, &zip, &LIMIT), "invalid entry path");
+
+        let tar = build_raw_tar("a/../../evil", tar::EntryType::Regular, None);
+        assert_error(extract_in(&dir, &tar, &LIMIT), "invalid entry path");
+
+        assert!(!dir.join("evil").exists());
+    }
+
+    #[test]
+    fn reject_links() {
+        let dir = TempDir::new("heng-archive-links").unwrap();
+
+        let tar = build_raw_tar("passwd", tar::EntryType::Symlink, Some("/etc/passwd"));
+        assert_error(extract_in(&dir, &tar, &LIMIT), "links are not allowed");
+
+        let tar = build_raw_tar("passwd", tar::EntryType::Link, Some("../../etc/passwd"));
+        assert_error(extract_in(&dir, &tar, &LIMIT), "links are not allowed");
+    }
+
+    #[test]
+    fn limits() {
+        let dir = TempDir::new("heng-archive-limits").unwrap();
+
+        let names: Vec<String> = (0..=LIMIT.max_entries).map(|i| i.to_string()).collect();
+        let files: Vec<(&str, &[u8])> = names.iter().map(|n| (n.as_str(), &b"x"[..])).collect();
+        assert_error(
+            extract_in(&dir, &build_tar(&files), &LIMIT),
+            "too many entries",
+        );
+        assert_error(
+            extract_in(&dir, &build_zip(&files), &LIMIT),
+            "too many entries",
+        );
+
+        // a decompression bomb
+        let zeros = vec![0; 4 * 1024 * 1024];
+        let bomb = zstd::encode_all(&*build_tar(&[("zeros", &zeros)]), 19).unwrap();
+        let limit = ExtractLimit {
+            max_uncompressed_size: u64::MAX,
+            ..LIMIT
+        };
+        assert_error(
+            extract_in(&dir, &bomb, &limit),
+            "compression ratio is too high",
+        );
+        let limit = ExtractLimit {
+            max_ratio: u64::MAX,
+            ..LIMIT
+        };
+        assert_error(
+            extract_in(&dir, &bomb, &limit),
+            "too large when uncompressed",
+        );
+
+        let random: Vec<u8> = (0..4096u32)
+            .map(|i| (i.wrapping_mul(2654435761) >> 13) as u8)
+            .collect();
+        let limit = ExtractLimit {
+            max_uncompressed_size: 4095,
+            ..LIMIT
+        };
+        let zip = build_zip(&[("random", &random)]);
+        assert_error(
+            extract_in(&dir, &zip, &limit),
+            "too large when uncompressed",
+        );
+        assert!(extract_in(&dir, &zip, &LIMIT).is_ok());
+    }
+
+    #[test]
+    fn enclosed() {
+        let dir = Path::new("/data/x");
+        assert_eq!(
+            enclosed_path(dir, Path::new("./a/b.in")).unwrap(),
+            Path::new("/data/x/a/b.in")
+        );
+        assert!(enclosed_path(dir, Path::new("../b.in")).is_err());
+        assert!(enclosed_path(dir, Path::new("/etc/passwd")).is_err());
+    }
+}
@@ -0,0 +1,353 @@
+use heng_protocol::common::DataCacheStatus;
+use heng_utils::crypto::Hashsum;
+
+use std::fs;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering::Relaxed};
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use lru::LruCache;
+use tracing::{info, warn};
+
+/// an index of unpacked data under the data directory
+///
+/// Data is named by its hashsum and evicted in LRU order when the total size
+/// exceeds the capacity. Data referenced by any handle is never evicted.
+pub struct DataCache {
+    directory: PathBuf,
+    capacity: u64,
+    state: Mutex<State>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+struct State {
+    entries: LruCache<String, Entry>,
+    total_size: u64,
+}
+
+struct Entry {
+    size: u64,
+    refs: usize,
+}
+
+/// a reference to unpacked data, which keeps it from eviction
+pub struct DataHandle {
+    cache: Arc<DataCache>,
+    path: PathBuf,
+    /// `None` for one-off data, which is removed on drop
+    name: Option<String>,
+}
+
+impl DataCache {
+    /// opens the cache and reconciles it with the data directory
+    ///
+    /// Named data on disk is indexed in the order of modification time.
+    /// Anything else is left by a previous run and is removed.
+    pub fn open(directory: PathBuf, capacity: u64) -> Result<Arc<Self>> {
+        let cache = Self::load(directory, capacity, true)?;
+        cache.evict();
+        Ok(cache)
+    }
+
+    /// opens the cache beside a judger which may be using the data directory
+    ///
+    /// Named data on disk is reused, but nothing on disk is removed or evicted.
+    pub fn open_shared(directory: PathBuf) -> Result<Arc<Self>> {
+        Self::load(directory, u64::MAX, false)
+    }
+
+    fn load(directory: PathBuf, capacity: u64, reconcile: bool) -> Result<Arc<Self>> {
+        let mut found = Vec::new();
+        for entry in fs::read_dir(&directory)? {
+            let entry = entry?;
+            let path = entry.path();
+            let file_type = entry.file_type()?;
+            let name = entry.file_name().into_string().ok();
+            match name {
+                Some(name) if file_type.is_dir() && is_data_name(&name) => {
+                    let mtime = entry.metadata()?.modified()?;
+                    let size = dir_size(&path)?;
+                    found.push((mtime, name, size));
+                }
+                _ if reconcile => remove_path(&path),
+                _ => {}
+            }
+        }
+        found.sort_by_key(|&(mtime, _, _)| mtime);
+
+        let mut state = State {
+            entries: LruCache::unbounded(),
+            total_size: 0,
+        };
+        for (_, name, size) in found {
+            state.total_size += size;
+            state.entries.put(name, Entry { size, refs: 0 });
+        }
+        info!(
+            count = state.entries.len(),
+            total_size = state.total_size,
+            "data cache loaded"
+        );
+
+        Ok(Arc::new(Self {
+            directory,
+            capacity,
+            state: Mutex::new(state),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }))
+    }
+
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.directory.join(name)
+    }
+
+    /// returns a handle if the data is cached
+    pub fn acquire(self: &Arc<Self>, name: &str) -> Option<DataHandle> {
+        let mut state = self.state.lock().unwrap();
+        let entry = state.entries.get_mut(&name.to_owned())?;
+        entry.refs += 1;
+        self.hits.fetch_add(1, Relaxed);
+        Some(self.handle(name, true))
+    }
+
+    /// indexes the data which has been unpacked to `self.path(name)`
+    pub fn insert(self: &Arc<Self>, name: &str) -> Result<DataHandle> {
+        self.misses.fetch_add(1, Relaxed);
+        let size = dir_size(&self.path(name))?;
+        {
+            let mut state = self.state.lock().unwrap();
+            match state.entries.get_mut(&name.to_owned()) {
+                Some(entry) => entry.refs += 1,
+                None => {
+                    state.total_size += size;
+                    let entry = Entry { size, refs: 1 };
+                    state.entries.put(name.to_owned(), entry);
+                }
+            }
+        }
+        self.evict();
+        Ok(self.handle(name, true))
+    }
+
+    /// wraps one-off data, which is removed when the handle is dropped
+    pub fn temporary(self: &Arc<Self>, name: &str) -> DataHandle {
+        self.handle(name, false)
+    }
+
+    pub fn status(&self) -> DataCacheStatus {
+        let state = self.state.lock().unwrap();
+        DataCacheStatus {
+            hashsums: state
+                .entries
+                .iter()
+                .filter_map(|(name, _)| Hashsum::parse_file_name(name))
+                .map(|h| h.to_string())
+                .collect(),
+            hits: self.hits.load(Relaxed),
+            misses: self.misses.load(Relaxed),
+        }
+    }
+
+    fn handle(self: &Arc<Self>, name: &str, cached: bool) -> DataHandle {
+        DataHandle {
+            cache: Arc::clone(self),
+            path: self.path(name),
+            name: if cached { Some(name.to_owned()) } else { None },
+        }
+    }
+
+    fn release(&self, name: &str) {
+        {
+            let mut state = self.state.lock().unwrap();
+            if let Some(entry) = state.entries.peek_mut(&name.to_owned()) {
+                entry.refs -= 1;
+            }
+        }
+        self.evict();
+    }
+
+    /// removes unreferenced data in LRU order until the total size fits
+    fn evict(&self) {
+        let victims = {
+            let mut state = self.state.lock().unwrap();
+            let mut excess = state.total_size.saturating_sub(self.capacity);
+            let mut victims = Vec::new();
+            for (name, entry) in state.entries.iter().rev() {
+                if excess == 0 {
+                    break;
+                }
+                if entry.refs == 0 {
+                    excess = excess.saturating_sub(entry.size);
+                    victims.push(name.clone());
+                }
+            }
+            for name in &victims {
+                if let Some(entry) = state.entries.pop(name) {
+                    state.total_size -= entry.size;
+                }
+            }
+
+            // moves victims away before unlocking,
+            // so that the same data can be published again while removing
+            victims
+                .into_iter()
+                .filter_map(|name| {
+                    info!(%name, "evict data");
+                    let path = self.path(&name);
+                    let trash = self.path(&format!("{}.evicted", name));
+                    match fs::rename(&path, &trash) {
+                        Ok(()) => Some(trash),
+                        Err(err) => {
+                            warn!(path = %path.display(), %err, "failed to evict data");
+                            None
+                        }
+                    }
+                })
+                .collect::<Vec<_>>()
+        };
+
+        for path in victims {
+            remove_path(&path);
+        }
+    }
+}
+
+impl Deref for DataHandle {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl AsRef<Path> for DataHandle {
+    fn as_ref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for DataHandle {
+    fn drop(&mut self) {
+        match self.name {
+            Some(ref name) => self.cache.release(name),
+            None => remove_path(&self.path),
+        }
+    }
+}
+
+/// whether the directory is named by an addressable hashsum
+fn is_data_name(name: &str) -> bool {
+    Hashsum::parse_file_name(name).map_or(false, |hashsum| hashsum.algorithm.is_addressable())
+}
+
+/// total size of regular files, without following links
+fn dir_size(path: &Path) -> Result<u64> {
+    let mut size = 0;
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            size += dir_size(&entry.path())?;
+        } else if file_type.is_file() {
+            size += entry.metadata()?.len();
+        }
+    }
+    Ok(size)
+}
+
+fn remove_path(path: &Path) {
+    let result = match fs::symlink_metadata(path) {
+        Ok(meta) if meta.is_dir() => fs::remove_dir_all(path),
+        Ok(_) => fs::remove_file(path),
+        Err(_) => return,
+    };
+    if let Err(err) = result {
+        warn!(path = %path.display(), %err, "failed to remove data");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use heng_utils::temp_dir::TempDir;
+
+    fn name(i: u8) -> String {
+        format!("{:02x}", i).repeat(32)
+    }
+
+    fn write_data(cache: &DataCache, name: &str, size: usize) {
+        let dir = cache.path(name);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("1.in"), vec![b'x'; size]).unwrap();
+    }
+
+    #[test]
+    fn lru_eviction() {
+        let dir = TempDir::new("heng-cache").unwrap();
+
+        // leftovers of a previous run
+        fs::create_dir_all(dir.join("1614556800000000000-042")).unwrap();
+        fs::write(dir.join(format!("{}.archive", name(9))), b"").unwrap();
+        let cache = DataCache::open(dir.path().to_owned(), 250).unwrap();
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+
+        let (a, b, c) = (name(1), name(2), name(3));
+        write_data(&cache, &a, 100);
+        drop(cache.insert(&a).unwrap());
+        write_data(&cache, &b, 100);
+        let hb = cache.insert(&b).unwrap();
+        assert!(cache.acquire(&a).is_some());
+
+        // b is in use, so the least recently used a is evicted instead
+        write_data(&cache, &c, 100);
+        let hc = cache.insert(&c).unwrap();
+        assert!(!cache.path(&a).exists());
+        assert!(cache.acquire(&a).is_none());
+        assert!(hb.join("1.in").exists());
+
+        drop(hb);
+        drop(hc);
+        assert!(cache.path(&b).exists());
+        assert!(cache.path(&c).exists());
+
+        // reopening keeps the named data
+        drop(cache);
+        let cache = DataCache::open(dir.path().to_owned(), 250).unwrap();
+        assert!(cache.acquire(&b).is_some());
+
+        // one-off data is removed on drop
+        let tmp = "1614556800000000000-043";
+        fs::create_dir_all(cache.path(tmp)).unwrap();
+        drop(cache.temporary(tmp));
+        assert!(!cache.path(tmp).exists());
+    }
+
+    #[test]
+    fn shared() {
+        let dir = TempDir::new("heng-cache").unwrap();
+        let (a, b) = (name(1), name(2));
+
+        let cache = DataCache::open(dir.path().to_owned(), 150).unwrap();
+        write_data(&cache, &a, 100);
+        drop(cache.insert(&a).unwrap());
+
+        // the judger is unpacking other data
+        let unpacking = dir.join(format!("{}.archive", name(9)));
+        fs::write(&unpacking, b"").unwrap();
+
+        let shared = DataCache::open_shared(dir.path().to_owned()).unwrap();
+        assert!(unpacking.exists());
+        assert!(shared.acquire(&a).is_some());
+
+        // the capacity of the judger is not enforced
+        write_data(&shared, &b, 100);
+        drop(shared.insert(&b).unwrap());
+        assert!(cache.path(&a).exists());
+        assert!(cache.path(&b).exists());
+    }
+}
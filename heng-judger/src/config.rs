@@ -30,8 +30,54 @@ pub struct Judger {
     #[validate(length(min = 1))]
     pub secret_key: String,
 
+    /// reported to the controller
+    #[validate(length(min = 1, max = 256))]
+    pub name: Option<String>,
+
+    /// the max number of judges which run at the same time
+    #[validate(custom = "validate_max_concurrency")]
+    pub max_concurrency: MaxConcurrency,
+
     #[validate(range(min = 1000, max = 60000))]
     pub rpc_timeout: u64, // in milliseconds
+
+    #[validate(range(min = 1, max = 65536))]
+    pub rpc_max_inflight: usize,
+
+    #[validate(range(min = 100, max = 60000))]
+    pub progress_interval: u64, // in milliseconds
+
+    #[validate]
+    pub reconnect: Reconnect,
+
+    /// how long running judges are waited for on shutdown
+    #[validate(range(max = 3600000))]
+    pub shutdown_timeout: u64, // in milliseconds
+}
+
+/// a fixed number, or `"auto"` which is derived from cores and memory
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum MaxConcurrency {
+    Fixed(u32),
+    Auto(Auto),
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Auto {
+    Auto,
+}
+
+/// the backoff of reconnecting to the controller
+#[derive(Debug, Clone, Validate, Serialize, Deserialize)]
+pub struct Reconnect {
+    /// the delay before the first reconnect, which is doubled for each failure
+    #[validate(range(min = 1, max = 60000))]
+    pub initial_backoff: u64, // in milliseconds
+
+    #[validate(range(min = 1, max = 600000))]
+    pub max_backoff: u64, // in milliseconds
 }
 
 #[derive(Debug, Clone, Validate, Serialize, Deserialize)]
@@ -40,6 +86,44 @@ pub struct Data {
     pub directory: PathBuf,
 
     pub download_size_limit: ByteUnit,
+
+    /// total size of unpacked data, which is evicted in LRU order
+    pub cache_size: ByteUnit,
+
+    #[validate]
+    pub extract_limit: ExtractLimit,
+
+    #[validate]
+    pub download: Download,
+}
+
+#[derive(Debug, Clone, Validate, Serialize, Deserialize)]
+pub struct Download {
+    #[validate(range(min = 100, max = 600000))]
+    pub connect_timeout: u64, // in milliseconds
+
+    /// the max interval between two chunks of a response body
+    #[validate(range(min = 100, max = 600000))]
+    pub idle_timeout: u64, // in milliseconds
+
+    #[validate(range(max = 16))]
+    pub retries: u32,
+
+    /// the delay before the first retry, which is doubled for each retry
+    #[validate(range(min = 1, max = 60000))]
+    pub retry_backoff: u64, // in milliseconds
+}
+
+/// limits of extracting data archives
+#[derive(Debug, Clone, Validate, Serialize, Deserialize)]
+pub struct ExtractLimit {
+    pub max_uncompressed_size: ByteUnit,
+
+    #[validate(range(min = 1))]
+    pub max_entries: u64,
+
+    #[validate(range(min = 1))]
+    pub max_ratio: u64,
 }
 
 #[derive(Debug, Clone, Validate, Serialize, Deserialize)]
@@ -53,6 +137,12 @@ pub struct Executor {
     #[validate]
     pub hard_limit: HardLimit,
 
+    #[validate]
+    pub diagnostics: Diagnostics,
+
+    /// runs a hello-world program of each language on startup and disables failing ones
+    pub self_test: bool,
+
     #[validate]
     pub c_cpp: CCpp,
 
@@ -78,6 +168,14 @@ pub struct HardLimit {
     pub pids: u32,
 }
 
+/// size limits of excerpts in case results
+#[derive(Debug, Clone, Validate, Serialize, Deserialize)]
+pub struct Diagnostics {
+    pub output: ByteUnit,
+    pub difference: ByteUnit,
+    pub message: ByteUnit,
+}
+
 #[derive(Debug, Clone, Validate, Serialize, Deserialize)]
 pub struct CCpp {
     pub gcc: PathBuf,
@@ -144,6 +242,15 @@ fn validate_absolute_path(path: &PathBuf) -> Result<(), ValidationError> {
     Ok(())
 }
 
+fn validate_max_concurrency(value: &MaxConcurrency) -> Result<(), ValidationError> {
+    match *value {
+        MaxConcurrency::Fixed(n) if !(1..=64).contains(&n) => {
+            Err(ValidationError::new("requires 1 to 64"))
+        }
+        _ => Ok(()),
+    }
+}
+
 fn validate_binary_file_path(path: &PathBuf) -> Result<(), ValidationError> {
     if !path.is_absolute() {
         return Err(ValidationError::new("requires absolute path"));
@@ -1,37 +1,56 @@
-use crate::Config;
+use crate::archive::{self, ExtractLimit};
+use crate::cache::{DataCache, DataHandle};
+use crate::config::{self, Config};
 
 use heng_protocol::common as hp_common;
-use heng_utils::crypto::{hex_sha256, is_hex_sha256_format, to_hex_string};
-use hp_common::File;
+use heng_protocol::signature::calc_signature;
+use heng_utils::crypto::{hex_digest, hex_digest_reader, HashAlgorithm, Hashsum};
+use hp_common::{DataCacheStatus, File};
 
+use std::collections::BTreeMap;
 use std::fs;
-use std::io::{BufReader, Write};
+use std::io::{Seek, SeekFrom, Write};
 use std::path::Path;
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::{Context, Result};
 use chrono::Utc;
+use dashmap::DashMap;
 use futures::StreamExt;
+use http::{HeaderMap, HeaderValue, Method};
 use rand::Rng;
-use sha2::{Digest, Sha256};
+use reqwest::{header, StatusCode};
+use tokio::sync::Mutex;
+use tokio::time;
 use tracing::{error, warn};
-use zip::ZipArchive;
 
 pub struct DataModule {
     directory: PathBuf,
     download_size_limit: u64,
-}
-
-fn unzip(file_path: &Path, target_dir: &Path) -> Result<()> {
-    let file = fs::File::open(file_path)?;
-    let reader = BufReader::with_capacity(4 * 1024 * 1024, file);
-    let mut zip = ZipArchive::new(reader)?;
-    zip.extract(target_dir)?;
-    Ok(())
+    extract_limit: ExtractLimit,
+    cache: Arc<DataCache>,
+    client: reqwest::Client,
+    download: config::Download,
+    remote_domain: String,
+    access_key: String,
+    secret_key: String,
+    /// a lock for each hashsum being loaded
+    loading: DashMap<String, Arc<Mutex<()>>>,
 }
 
 impl DataModule {
     pub fn new(config: &Config) -> Result<Self> {
+        Self::build(config, false)
+    }
+
+    /// shares the data directory with a judger, see [`DataCache::open_shared`]
+    pub fn shared(config: &Config) -> Result<Self> {
+        Self::build(config, true)
+    }
+
+    fn build(config: &Config, shared: bool) -> Result<Self> {
         let directory = &config.data.directory;
         if !directory.exists() {
             fs::create_dir_all(directory).with_context(|| {
@@ -41,51 +60,66 @@ impl DataModule {
 
         let download_size_limit = config.data.download_size_limit.as_u64();
 
+        let extract_limit = {
+            let limit = &config.data.extract_limit;
+            ExtractLimit {
+                max_uncompressed_size: limit.max_uncompressed_size.as_u64(),
+                max_entries: limit.max_entries,
+                max_ratio: limit.max_ratio,
+            }
+        };
+
+        let cache = if shared {
+            DataCache::open_shared(directory.clone())?
+        } else {
+            DataCache::open(directory.clone(), config.data.cache_size.as_u64())?
+        };
+
+        let download = config.data.download.clone();
+        let client = reqwest::Client::builder()
+            .connect_timeout(Duration::from_millis(download.connect_timeout))
+            .build()?;
+
         Ok(Self {
             directory: directory.clone(),
             download_size_limit,
+            extract_limit,
+            cache,
+            client,
+            download,
+            remote_domain: config.judger.remote_domain.clone(),
+            access_key: config.judger.access_key.clone(),
+            secret_key: config.judger.secret_key.clone(),
+            loading: DashMap::new(),
         })
     }
 
     pub async fn download_file(&self, file: &File, path: &Path) -> Result<()> {
-        let size_limit = self.download_size_limit;
-        let (hashsum, content_hash) = match *file {
+        let hashsum = match file.hashsum() {
+            Some(h) => Some(parse_hashsum(h)?),
+            None => None,
+        };
+        let algorithm = hashsum.map_or(HashAlgorithm::Sha256, |h| h.algorithm);
+
+        let content_hash = match *file {
             File::Url {
                 ref url,
-                ref hashsum,
+                ref headers,
+                ref token,
+                ..
             } => {
-                let res = reqwest::get(url).await?;
-                if !res.status().is_success() {
-                    anyhow::bail!("request failed: status = {}", res.status());
-                }
-
-                let mut stream = res.bytes_stream();
-                let mut file = fs::File::create(&path)?;
-                let mut hasher = Sha256::new();
-                let mut size: u64 = 0;
-
-                while let Some(chunk) = stream.next().await {
-                    let chunk = chunk?;
-                    let len = chunk.len() as u64;
-                    size += len;
-                    if size > size_limit {
-                        anyhow::bail!(
-                            "body is too large: size = {}, size_limit = {}",
-                            size,
-                            size_limit
-                        );
-                    }
-                    hasher.update(&chunk);
-                    file.write_all(&chunk)?;
-                }
-
-                let content_hash = to_hex_string(hasher.finalize().as_ref());
-                (hashsum, content_hash)
+                let request = UrlRequest {
+                    url,
+                    headers: headers.as_ref(),
+                    token: token.as_deref(),
+                };
+                self.download_url(&request, path).await?;
+                hex_digest_file(algorithm, path)?
             }
             File::Direct {
                 ref content,
-                ref hashsum,
                 base64,
+                ..
             } => {
                 let base64_decoded;
                 let content_bytes = if base64 {
@@ -94,15 +128,38 @@ impl DataModule {
                 } else {
                     content.as_bytes()
                 };
-                let content_hash = hex_sha256(content_bytes);
+                let content_hash = hex_digest(algorithm, content_bytes);
                 fs::write(path, content_bytes)?;
 
-                (hashsum, content_hash)
+                content_hash
+            }
+            File::Binary { ref content, .. } => {
+                let content_hash = hex_digest(algorithm, content);
+                fs::write(path, content)?;
+
+                content_hash
+            }
+            File::Stored { .. } => {
+                let hashsum = hashsum.context("stored file without hashsum")?;
+                if !hashsum.algorithm.is_addressable() {
+                    anyhow::bail!(
+                        "stored file is not addressable by {}",
+                        hashsum.algorithm.name()
+                    );
+                }
+                let (url, headers) = self.stored_file_request(hashsum)?;
+                let request = UrlRequest {
+                    url: &url,
+                    headers: Some(&headers),
+                    token: None,
+                };
+                self.download_url(&request, path).await?;
+                hex_digest_file(algorithm, path)?
             }
         };
 
         if let Some(hashsum) = hashsum {
-            if content_hash != *hashsum {
+            if content_hash != hashsum.hex {
                 error!(?content_hash, expected=?hashsum,"file hashsum mismatch");
                 anyhow::bail!("file hashsum mismatch");
             }
@@ -111,46 +168,398 @@ impl DataModule {
         Ok(())
     }
 
-    pub async fn load_data(&self, file: &File) -> Result<PathBuf> {
-        let hashsum = match file {
-            File::Url { ref hashsum, .. } => hashsum.as_deref(),
-            File::Direct { ref hashsum, .. } => hashsum.as_deref(),
-        };
+    /// a signed request to the file store of the controller
+    fn stored_file_request(
+        &self,
+        hashsum: Hashsum<'_>,
+    ) -> Result<(String, BTreeMap<String, String>)> {
+        let path = format!("/v1/files/{}", hashsum.file_name());
+        let url = format!("http://{}{}", self.remote_domain, path);
 
-        let generated_name;
-        let data_name = match hashsum {
-            Some(h) => {
-                if !is_hex_sha256_format(h) {
-                    error!(?h, "invalid file hashsum");
-                    anyhow::bail!("invalid file hashsum")
+        let mut headers = HeaderMap::new();
+        headers.insert("x-heng-accesskey", HeaderValue::from_str(&self.access_key)?);
+        let signature = calc_signature(&Method::GET, &path, "", &headers, &[], &self.secret_key);
+
+        let headers = vec![
+            ("x-heng-accesskey".to_owned(), self.access_key.clone()),
+            ("x-heng-signature".to_owned(), signature),
+        ];
+        Ok((url, headers.into_iter().collect()))
+    }
+
+    /// downloads a url with retries
+    ///
+    /// A retry resumes the partial file by a range request if the server supports it.
+    async fn download_url(&self, request: &UrlRequest<'_>, path: &Path) -> Result<()> {
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(path)?;
+
+        let mut attempt = 0;
+        loop {
+            let err = match self.download_once(request, &mut file).await {
+                Ok(()) => return Ok(()),
+                Err(DownloadError::Fatal(err)) => return Err(err),
+                Err(DownloadError::Retryable(err)) => err,
+            };
+            if attempt >= self.download.retries {
+                return Err(err.context(format!("download failed after {} retries", attempt)));
+            }
+            let delay = self.download.retry_backoff << attempt.min(16);
+            attempt += 1;
+            warn!(url = %request.url, %err, attempt, delay, "download failed, retrying");
+            time::sleep(Duration::from_millis(delay)).await;
+        }
+    }
+
+    async fn download_once(
+        &self,
+        request: &UrlRequest<'_>,
+        file: &mut fs::File,
+    ) -> Result<(), DownloadError> {
+        let size_limit = self.download_size_limit;
+        let mut size = file.metadata().map_err(DownloadError::fatal)?.len();
+
+        let mut builder = self.client.get(request.url);
+        if let Some(headers) = request.headers {
+            for (name, value) in headers {
+                builder = builder.header(name.as_str(), value.as_str());
+            }
+        }
+        if let Some(token) = request.token {
+            builder = builder.bearer_auth(token);
+        }
+        if size > 0 {
+            builder = builder.header(header::RANGE, format!("bytes={}-", size));
+        }
+
+        let res = builder.send().await.map_err(DownloadError::retryable)?;
+        let status = res.status();
+        if status == StatusCode::PARTIAL_CONTENT {
+            file.seek(SeekFrom::End(0)).map_err(DownloadError::fatal)?;
+        } else if status.is_success() {
+            // the server ignores the range, so restarts from the beginning
+            file.set_len(0).map_err(DownloadError::fatal)?;
+            file.seek(SeekFrom::Start(0))
+                .map_err(DownloadError::fatal)?;
+            size = 0;
+        } else {
+            let err = anyhow::format_err!("request failed: status = {}", status);
+            let retryable = status.is_server_error()
+                || status == StatusCode::REQUEST_TIMEOUT
+                || status == StatusCode::TOO_MANY_REQUESTS;
+            return Err(if retryable {
+                DownloadError::Retryable(err)
+            } else {
+                DownloadError::Fatal(err)
+            });
+        }
+
+        let idle_timeout = Duration::from_millis(self.download.idle_timeout);
+        let mut stream = res.bytes_stream();
+        loop {
+            let chunk = match time::timeout(idle_timeout, stream.next()).await {
+                Ok(Some(chunk)) => chunk.map_err(DownloadError::retryable)?,
+                Ok(None) => break,
+                Err(_) => {
+                    let err = anyhow::format_err!("no response for {:?}", idle_timeout);
+                    return Err(DownloadError::Retryable(err));
                 }
-                h
+            };
+            size += chunk.len() as u64;
+            if size > size_limit {
+                return Err(DownloadError::Fatal(anyhow::format_err!(
+                    "body is too large: size = {}, size_limit = {}",
+                    size,
+                    size_limit
+                )));
             }
-            None => {
-                let timestamp = Utc::now().timestamp_nanos();
-                let rng = rand::thread_rng().gen_range(0..1000);
-                generated_name = format!("{}-{:03}", timestamp, rng);
-                &generated_name
+            file.write_all(&chunk).map_err(DownloadError::fatal)?;
+        }
+        Ok(())
+    }
+
+    pub fn cache_status(&self) -> DataCacheStatus {
+        self.cache.status()
+    }
+
+    /// loads data into the cache
+    ///
+    /// Loading of the same hashsum is deduplicated. Data is downloaded and
+    /// extracted under a unique temporary name, and published by an atomic
+    /// rename after the hashsum is verified. So a partial extraction is never
+    /// treated as a cache hit.
+    pub async fn load_data(&self, file: &File) -> Result<DataHandle> {
+        let hashsum = match file.hashsum() {
+            Some(h) => Some(parse_hashsum(h)?),
+            None => None,
+        };
+        let data_name = match hashsum {
+            Some(h) if h.algorithm.is_addressable() => h.file_name(),
+            _ => {
+                // data without an addressable hashsum can not be reused
+                let data_name = unique_name("data");
+                self.unpack(file, &data_name).await?;
+                return Ok(self.cache.temporary(&data_name));
             }
         };
 
-        let dir_path = self.directory.join(data_name);
-        if dir_path.exists() {
-            return Ok(dir_path);
+        let data_name = &*data_name;
+        if let Some(handle) = self.cache.acquire(data_name) {
+            return Ok(handle);
+        }
+
+        let lock = self
+            .loading
+            .entry(data_name.to_owned())
+            .or_default()
+            .clone();
+        let _loading = lock.lock().await;
+        // waiters hold the lock, so it is removed only by the last holder
+        let _remove_lock = scopeguard::guard((), |()| {
+            self.loading
+                .remove_if(data_name, |_, lock| Arc::strong_count(lock) <= 2);
+        });
+
+        // loaded by another judge while waiting
+        if let Some(handle) = self.cache.acquire(data_name) {
+            return Ok(handle);
+        }
+
+        let temp_name = unique_name(data_name);
+        let temp_dir = self.unpack(file, &temp_name).await?;
+        let dir_path = self.cache.path(data_name);
+        if let Err(err) = fs::rename(&temp_dir, &dir_path) {
+            remove_dir(&temp_dir);
+            return Err(anyhow::Error::new(err).context("failed to publish data"));
         }
 
-        let zip_path = scopeguard::guard(
-            self.directory.join(format!("{}.zip", data_name)),
-            |zip_path| {
-                if let Err(err) = fs::remove_file(&zip_path) {
-                    warn!(zip_path = %zip_path.display(), %err, "failed to remove zip file");
+        self.cache.insert(data_name)
+    }
+
+    /// downloads and extracts data into `data_name`
+    ///
+    /// The hashsum is verified before extraction. The directory is removed on failure.
+    async fn unpack(&self, file: &File, data_name: &str) -> Result<PathBuf> {
+        let dir_path = self.cache.path(data_name);
+
+        let archive_path = scopeguard::guard(
+            self.directory.join(format!("{}.archive", data_name)),
+            |archive_path| {
+                if let Err(err) = fs::remove_file(&archive_path) {
+                    warn!(path = %archive_path.display(), %err, "failed to remove archive");
                 }
             },
         );
 
-        self.download_file(&file, &*zip_path).await?;
-        unzip(&zip_path, &dir_path).context("failed to unzip")?;
+        self.download_file(&file, &*archive_path).await?;
+        if let Err(err) = archive::extract(&archive_path, &dir_path, &self.extract_limit) {
+            remove_dir(&dir_path);
+            return Err(err.context("failed to extract archive"));
+        }
 
         Ok(dir_path)
     }
 }
+
+struct UrlRequest<'a> {
+    url: &'a str,
+    headers: Option<&'a BTreeMap<String, String>>,
+    token: Option<&'a str>,
+}
+
+enum DownloadError {
+    Retryable(anyhow::Error),
+    Fatal(anyhow::Error),
+}
+
+impl DownloadError {
+    fn retryable(err: impl Into<anyhow::Error>) -> Self {
+        Self::Retryable(err.into())
+    }
+
+    fn fatal(err: impl Into<anyhow::Error>) -> Self {
+        Self::Fatal(err.into())
+    }
+}
+
+fn parse_hashsum(hashsum: &str) -> Result<Hashsum<'_>> {
+    match Hashsum::parse(hashsum) {
+        Some(h) => Ok(h),
+        None => {
+            error!(?hashsum, "invalid file hashsum");
+            anyhow::bail!("invalid file hashsum")
+        }
+    }
+}
+
+fn hex_digest_file(algorithm: HashAlgorithm, path: &Path) -> Result<String> {
+    let mut file = fs::File::open(path)?;
+    Ok(hex_digest_reader(algorithm, &mut file)?)
+}
+
+/// generates a name which is never a hashsum
+fn unique_name(prefix: &str) -> String {
+    let timestamp = Utc::now().timestamp_nanos();
+    let rng = rand::thread_rng().gen_range(0..1000);
+    format!("{}.tmp-{}-{:03}", prefix, timestamp, rng)
+}
+
+fn remove_dir(path: &Path) {
+    if let Err(err) = fs::remove_dir_all(path) {
+        warn!(path = %path.display(), %err, "failed to remove partial data");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use heng_utils::temp_dir::TempDir;
+
+    fn data_module(name: &str, retries: u32) -> (TempDir, DataModule) {
+        let dir = TempDir::new(&format!("heng-{}", name)).unwrap();
+        let directory = dir.path().to_owned();
+
+        let data_module = DataModule {
+            directory: directory.clone(),
+            download_size_limit: 1024 * 1024,
+            extract_limit: ExtractLimit {
+                max_uncompressed_size: u64::MAX,
+                max_entries: 16,
+                max_ratio: 100,
+            },
+            cache: DataCache::open(directory, u64::MAX).unwrap(),
+            client: reqwest::Client::new(),
+            download: config::Download {
+                connect_timeout: 1000,
+                idle_timeout: 1000,
+                retries,
+                retry_backoff: 1,
+            },
+            remote_domain: "localhost".into(),
+            access_key: "ak".into(),
+            secret_key: "sk".into(),
+            loading: DashMap::new(),
+        };
+        (dir, data_module)
+    }
+
+    #[tokio::test]
+    async fn single_flight() {
+        let (dir, data_module) = data_module("data", 0);
+        let directory = dir.path();
+
+        let content = {
+            let mut builder = tar::Builder::new(Vec::new());
+            let mut header = tar::Header::new_gnu();
+            header.set_size(3);
+            header.set_mode(0o644);
+            builder
+                .append_data(&mut header, "1.in", &b"1 2"[..])
+                .unwrap();
+            builder.into_inner().unwrap()
+        };
+        let file = File::Binary {
+            hashsum: Some(format!(
+                "blake3:{}",
+                hex_digest(HashAlgorithm::Blake3, &content)
+            )),
+            content,
+        };
+
+        let (lhs, rhs) = tokio::join!(data_module.load_data(&file), data_module.load_data(&file));
+        let (lhs, rhs) = (lhs.unwrap(), rhs.unwrap());
+        assert_eq!(&*lhs, &*rhs);
+        assert_eq!(fs::read(lhs.join("1.in")).unwrap(), b"1 2");
+
+        // nothing but the published data is left
+        assert_eq!(fs::read_dir(directory).unwrap().count(), 1);
+        assert!(data_module.loading.is_empty());
+
+        let mismatch = File::Binary {
+            hashsum: Some("0".repeat(64)),
+            content: b"bad".to_vec(),
+        };
+        assert!(data_module.load_data(&mismatch).await.is_err());
+        assert_eq!(fs::read_dir(directory).unwrap().count(), 1);
+
+        // the lock is kept for a waiter, so that it is never loaded concurrently
+        let mismatch_name = parse_hashsum(mismatch.hashsum().unwrap())
+            .unwrap()
+            .file_name();
+        let waiter = data_module
+            .loading
+            .entry(mismatch_name.clone())
+            .or_default()
+            .clone();
+        assert!(data_module.load_data(&mismatch).await.is_err());
+        assert!(data_module.loading.contains_key(&mismatch_name));
+        drop(waiter);
+
+        drop((lhs, rhs));
+    }
+
+    /// the first response is cut off, and the retry resumes with a range request
+    #[tokio::test]
+    async fn resume() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::TcpListener;
+
+        const BODY: &[u8] = b"hello, world";
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = tokio::spawn(async move {
+            let mut requests = Vec::new();
+            for i in 0..2 {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = vec![0; 4096];
+                let n = stream.read(&mut buf).await.unwrap();
+                let request = String::from_utf8_lossy(&buf[..n]).to_lowercase();
+                let response = if i == 0 {
+                    let mut res =
+                        format!("HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\r\n", BODY.len())
+                            .into_bytes();
+                    res.extend_from_slice(&BODY[..5]);
+                    res
+                } else {
+                    let mut res = format!(
+                        "HTTP/1.1 206 Partial Content\r\ncontent-length: {}\r\n\r\n",
+                        BODY.len() - 5
+                    )
+                    .into_bytes();
+                    res.extend_from_slice(&BODY[5..]);
+                    res
+                };
+                stream.write_all(&response).await.unwrap();
+                stream.shutdown().await.unwrap();
+                requests.push(request);
+            }
+            requests
+        });
+
+        let (_dir, data_module) = data_module("download", 1);
+        let path = data_module.directory.join("file");
+        let file = File::Url {
+            url: format!("http://{}/data", addr),
+            hashsum: Some(format!("md5:{}", hex_digest(HashAlgorithm::Md5, BODY))),
+            headers: Some(
+                vec![("x-data-key".to_owned(), "42".to_owned())]
+                    .into_iter()
+                    .collect(),
+            ),
+            token: Some("secret".into()),
+        };
+        data_module.download_file(&file, &path).await.unwrap();
+        assert_eq!(fs::read(&path).unwrap(), BODY);
+
+        let requests = server.await.unwrap();
+        assert!(requests[0].contains("authorization: bearer secret"));
+        assert!(requests[0].contains("x-data-key: 42"));
+        assert!(!requests[0].contains("range:"));
+        assert!(requests[1].contains("range: bytes=5-"));
+    }
+}
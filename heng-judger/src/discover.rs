@@ -0,0 +1,191 @@
+use heng_protocol::common::TestCase;
+
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use anyhow::Result;
+
+const MAX_DEPTH: usize = 8;
+
+/// discovers test cases in the data directory
+///
+/// An input file is paired with an output file by conventions:
+/// + `x.in` and `x.out` / `x.ans`
+/// + `input/x` and `output/x`, with the same conventions of extensions
+///
+/// The cases are sorted by input paths in natural order.
+pub fn discover_cases(data_dir: &Path) -> Result<Vec<TestCase>> {
+    let mut files = HashSet::new();
+    collect_files(data_dir, "", 0, &mut files)?;
+
+    let mut cases: Vec<TestCase> = files
+        .iter()
+        .filter_map(|input| {
+            let output = output_candidates(input)
+                .into_iter()
+                .find(|c| files.contains(c))?;
+            Some(TestCase {
+                input: input.clone(),
+                output,
+            })
+        })
+        .collect();
+
+    cases.sort_by(|lhs, rhs| natural_cmp(&lhs.input, &rhs.input));
+    Ok(cases)
+}
+
+/// collects relative paths of regular files, separated by `/`
+fn collect_files(
+    dir: &Path,
+    prefix: &str,
+    depth: usize,
+    files: &mut HashSet<String>,
+) -> Result<()> {
+    if depth > MAX_DEPTH {
+        return Ok(());
+    }
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = match entry.file_name().into_string() {
+            Ok(name) => name,
+            Err(_) => continue,
+        };
+        let path = format!("{}{}", prefix, name);
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            collect_files(&entry.path(), &format!("{}/", path), depth + 1, files)?;
+        } else if file_type.is_file() {
+            files.insert(path);
+        }
+    }
+    Ok(())
+}
+
+fn output_candidates(input: &str) -> Vec<String> {
+    fn with_extensions(path: &str, candidates: &mut Vec<String>) {
+        if let Some(stem) = path.strip_suffix(".in") {
+            candidates.push(format!("{}.out", stem));
+            candidates.push(format!("{}.ans", stem));
+        }
+    }
+
+    let mut candidates = Vec::new();
+    with_extensions(input, &mut candidates);
+
+    let components: Vec<&str> = input.split('/').collect();
+    if let Some(pos) = components.iter().rposition(|&c| c == "input") {
+        if pos + 1 < components.len() {
+            let mut components = components.clone();
+            components[pos] = "output";
+            let output = components.join("/");
+            with_extensions(&output, &mut candidates);
+            candidates.push(output);
+        }
+    }
+    candidates
+}
+
+/// compares strings with embedded numbers by their values
+pub fn natural_cmp(lhs: &str, rhs: &str) -> Ordering {
+    fn split_digits(s: &[u8]) -> (&[u8], &[u8]) {
+        let pos = s
+            .iter()
+            .position(|b| !b.is_ascii_digit())
+            .unwrap_or(s.len());
+        s.split_at(pos)
+    }
+
+    fn trim_zeros(s: &[u8]) -> &[u8] {
+        let pos = s.iter().position(|&b| b != b'0').unwrap_or(s.len());
+        &s[pos..]
+    }
+
+    let (mut a, mut b) = (lhs.as_bytes(), rhs.as_bytes());
+    loop {
+        match (a.first(), b.first()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let (na, ra) = split_digits(a);
+                let (nb, rb) = split_digits(b);
+                let (ta, tb) = (trim_zeros(na), trim_zeros(nb));
+                let ord = ta
+                    .len()
+                    .cmp(&tb.len())
+                    .then_with(|| ta.cmp(tb))
+                    .then_with(|| na.len().cmp(&nb.len()));
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+                a = ra;
+                b = rb;
+            }
+            (Some(x), Some(y)) => {
+                if x != y {
+                    return x.cmp(y);
+                }
+                a = &a[1..];
+                b = &b[1..];
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use heng_utils::temp_dir::TempDir;
+
+    #[test]
+    fn natural_order() {
+        let mut names = vec!["10.in", "2.in", "1.in", "a10.in", "a9.in", "01.in", "b.in"];
+        names.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(
+            names,
+            ["1.in", "01.in", "2.in", "10.in", "a9.in", "a10.in", "b.in"]
+        );
+    }
+
+    #[test]
+    fn discover() {
+        let dir = TempDir::new("heng-discover").unwrap();
+        let files = [
+            "10.in",
+            "10.out",
+            "2.in",
+            "2.ans",
+            "3.in",
+            "input/a1",
+            "output/a1",
+            "input/b2.in",
+            "output/b2.out",
+            "readme.txt",
+        ];
+        for file in &files {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, file).unwrap();
+        }
+
+        let cases = discover_cases(dir.path()).unwrap();
+
+        let pairs: Vec<(&str, &str)> = cases
+            .iter()
+            .map(|c| (c.input.as_str(), c.output.as_str()))
+            .collect();
+        assert_eq!(
+            pairs,
+            [
+                ("2.in", "2.ans"),
+                ("10.in", "10.out"),
+                ("input/a1", "output/a1"),
+                ("input/b2.in", "output/b2.out"),
+            ]
+        );
+    }
+}
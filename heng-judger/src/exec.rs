@@ -1,43 +1,137 @@
+use crate::config::{self, Config};
 use crate::data::DataModule;
-use crate::Config;
+use crate::discover;
+use crate::lang::{self, Language, Limit};
+use crate::polygon;
+use crate::sandbox::{Sandbox, SandboxOutput};
+use crate::score::{self, SubtaskJudge};
+use crate::verdict;
 
 use std::collections::HashSet;
 use std::fs;
-use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use std::io::Read;
+use std::mem;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
 
-use heng_protocol::common::{DynamicFile, File, Judge, JudgeResult, Test};
+use heng_protocol::common::{
+    CaseDiagnostics, DynamicFile, Executable, ExecutionInfo, Judge, JudgeCaseResult, JudgeResult,
+    JudgeResultExtra, JudgeResultKind, OutputDifference, SubtaskResult, Test, TestCase, TestPolicy,
+};
 use heng_protocol::error::ErrorCode;
-use heng_protocol::internal::ErrorInfo;
+use heng_protocol::internal::ws_json::CreateJudgeArgs;
+use heng_protocol::internal::{ErrorInfo, JudgeState};
 use heng_utils::auto_join::auto_join;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
+use futures::future::{self, Either};
+use nix::sys::stat::Mode;
+use nix::unistd::{self, Gid, Uid};
+use tokio::sync::watch;
+use tokio::task;
+use tracing::warn;
 
 pub struct ExecutorModule {
     data_module: Arc<DataModule>,
+    sandbox: Arc<dyn Sandbox>,
     workspace_root: PathBuf,
+    uid: u32,
+    gid: u32,
+    hard_limit: Limit,
+    diagnostics: config::Diagnostics,
+    /// languages which failed the self-test
+    disabled_languages: RwLock<HashSet<String>>,
+    /// workspaces of stopped judges which could not be removed
+    leftover_workspaces: Mutex<Vec<PathBuf>>,
+}
+
+/// the progress of a judge, which is sent after each case
+#[derive(Debug, Clone)]
+pub struct Progress {
+    pub state: JudgeState,
+    pub current_case: Option<u32>,
+    pub cases: Vec<JudgeCaseResult>,
+}
+
+/// an executable which is ready to run in its sandbox root
+struct Program {
+    lang: Arc<dyn Language>,
+    sandbox: Arc<dyn Sandbox>,
+    root: PathBuf,
+    limit: Limit,
+}
+
+enum Compiled {
+    Ok(Program),
+    Failed {
+        kind: JudgeResultKind,
+        message: Option<String>,
+    },
 }
 
 impl ExecutorModule {
-    pub fn new(config: &Config, data_module: Arc<DataModule>) -> Result<Self> {
+    pub fn new(
+        config: &Config,
+        data_module: Arc<DataModule>,
+        sandbox: Arc<dyn Sandbox>,
+    ) -> Result<Self> {
         let workspace_root = &config.executor.workspace_root;
         if !workspace_root.exists() {
             fs::create_dir_all(&workspace_root)?;
         }
+        let hard_limit = &config.executor.hard_limit;
         Ok(Self {
             data_module,
+            sandbox,
             workspace_root: workspace_root.clone(),
+            uid: config.executor.uid,
+            gid: config.executor.gid,
+            hard_limit: Limit {
+                real_time: hard_limit.real_time,
+                cpu_time: hard_limit.cpu_time,
+                memory: hard_limit.memory.as_u64(),
+                output: hard_limit.output.as_u64(),
+                pids: hard_limit.pids,
+            },
+            diagnostics: config.executor.diagnostics.clone(),
+            disabled_languages: RwLock::new(HashSet::new()),
+            leftover_workspaces: Mutex::new(Vec::new()),
         })
     }
 
+    pub fn sandbox(&self) -> &Arc<dyn Sandbox> {
+        &self.sandbox
+    }
+
+    /// names of the languages which are not disabled
+    pub fn languages(&self) -> Vec<String> {
+        let disabled = self.disabled_languages.read().unwrap();
+        lang::LANGUAGES
+            .iter()
+            .filter(|name| !disabled.contains(**name))
+            .map(|name| (*name).to_owned())
+            .collect()
+    }
+
+    /// replaces the disabled languages
+    pub fn disable_languages(&self, names: impl IntoIterator<Item = String>) {
+        *self.disabled_languages.write().unwrap() = names.into_iter().collect();
+    }
+
     pub async fn exec(
         &self,
-        id: Arc<str>,
-        data: Option<File>,
-        dynamic_files: Option<Vec<DynamicFile>>,
-        judge: Judge,
-        test: Test,
+        args: CreateJudgeArgs,
+        progress: &watch::Sender<Progress>,
     ) -> Result<JudgeResult> {
+        let CreateJudgeArgs {
+            id,
+            data,
+            dynamic_files,
+            mut judge,
+            mut test,
+            diagnostics,
+        } = args;
+
         // directory structure:
         //
         // - $workspace
@@ -46,10 +140,19 @@ impl ExecutorModule {
         //          - __spj_code
         //          - __interactor_code
         //          - $(dyn files)*
-        //      - run (the root of sandbox process)
+        //          - $(package resources)*
+        //      - run (the root of user process)
+        //      - spj (the root of spj process)
+        //      - interactor (the root of interactor process)
 
         // create workspace
-        let workspace = self.create_workspace(&*id)?;
+        let workspace = self.create_workspace(&id)?;
+        // removed when the judge stops, even if it is abandoned
+        let _guard = scopeguard::guard(workspace.clone(), |workspace| {
+            self.remove_workspace(workspace)
+        });
+
+        send_progress(progress, JudgeState::Preparing, None, &[]);
 
         // load data
         let data_dir = match data {
@@ -57,10 +160,33 @@ impl ExecutorModule {
             None => None,
         };
 
+        // import polygon package
+        let package = match data_dir {
+            Some(ref dir) if polygon::is_package(dir) => {
+                let package = polygon::load_package(dir)?;
+                package.apply(dir, &mut judge, &mut test)?;
+                Some(package)
+            }
+            _ => None,
+        };
+
         // create workspace/files
         let files_dir = workspace.join("files");
         fs::create_dir(&files_dir)?;
 
+        // copy package resources, such as testlib.h
+        let mut resources = Vec::new();
+        if let (Some(package), Some(data_dir)) = (&package, &data_dir) {
+            for path in &package.resources {
+                let name = match Path::new(path).file_name().and_then(|n| n.to_str()) {
+                    Some(name) => name,
+                    None => continue,
+                };
+                fs::copy(data_dir.join(path), files_dir.join(name))?;
+                resources.push(name.to_owned());
+            }
+        }
+
         // load dynamic files
         if let Some(ref dyn_files) = dynamic_files {
             self.load_dyn_files(&files_dir, dyn_files).await?;
@@ -69,17 +195,542 @@ impl ExecutorModule {
         // load sources
         self.load_sources(&files_dir, &judge).await?;
 
-        // create workspace/run
-        let run_dir = workspace.join("run");
-        fs::create_dir(&run_dir)?;
+        // dynamic files and package resources are visible to spj and interactor
+        let shared_files: Vec<&str> = dynamic_files
+            .iter()
+            .flatten()
+            .map(|f| match f {
+                DynamicFile::BuiltIn { name } => name.as_str(),
+                DynamicFile::Remote { name, .. } => name.as_str(),
+            })
+            .chain(resources.iter().map(String::as_str))
+            .collect();
+
+        let (user, spj, interactor) = match judge {
+            Judge::Normal { ref user } => (user, None, None),
+            Judge::Special { ref user, ref spj } => (user, Some(spj), None),
+            // the checker of an interactive judge is prepared as a special judge
+            Judge::Interactive {
+                ref user,
+                ref interactor,
+                ref checker,
+            } => (user, checker.as_ref(), Some(interactor)),
+        };
+
+        let discovered_cases = if test.cases.is_empty() || test.discover {
+            let data_dir = match data_dir {
+                Some(ref dir) => dir,
+                None => reject_error!(
+                    ErrorCode::InvalidRequest,
+                    Some("can not discover cases without data".to_owned())
+                ),
+            };
+            test.cases = discover::discover_cases(data_dir)?;
+            Some(test.cases.clone())
+        } else {
+            None
+        };
+
+        let mut subtask_judge = match test.subtasks {
+            Some(ref subtasks) => Some(SubtaskJudge::new(subtasks, test.cases.len())?),
+            None => None,
+        };
+
+        let user = match self
+            .prepare(&workspace, &files_dir, "run", "__user_code", user, &[])
+            .await?
+        {
+            Compiled::Ok(program) => program,
+            Compiled::Failed { kind, message } => {
+                let info = ExecutionInfo {
+                    compile_message: message,
+                };
+                return Ok(failed_result(
+                    &test,
+                    discovered_cases,
+                    kind,
+                    Some(info),
+                    None,
+                    None,
+                ));
+            }
+        };
+
+        let spj = match spj {
+            None => None,
+            Some(spj) => match self
+                .prepare(
+                    &workspace,
+                    &files_dir,
+                    "spj",
+                    "__spj_code",
+                    spj,
+                    &shared_files,
+                )
+                .await?
+            {
+                Compiled::Ok(program) => Some(program),
+                Compiled::Failed { message, .. } => {
+                    let info = ExecutionInfo {
+                        compile_message: message,
+                    };
+                    let kind = JudgeResultKind::SystemCompileError;
+                    return Ok(failed_result(
+                        &test,
+                        discovered_cases,
+                        kind,
+                        None,
+                        Some(info),
+                        None,
+                    ));
+                }
+            },
+        };
+
+        let interactor = match interactor {
+            None => None,
+            Some(interactor) => match self
+                .prepare(
+                    &workspace,
+                    &files_dir,
+                    "interactor",
+                    "__interactor_code",
+                    interactor,
+                    &shared_files,
+                )
+                .await?
+            {
+                Compiled::Ok(program) => Some(program),
+                Compiled::Failed { message, .. } => {
+                    let info = ExecutionInfo {
+                        compile_message: message,
+                    };
+                    let kind = JudgeResultKind::SystemCompileError;
+                    return Ok(failed_result(
+                        &test,
+                        discovered_cases,
+                        kind,
+                        None,
+                        None,
+                        Some(info),
+                    ));
+                }
+            },
+        };
+
+        // test cases are relative to the data directory
+        let base_dir = data_dir.as_deref().unwrap_or(&files_dir);
+
+        let mut cases = Vec::with_capacity(test.cases.len());
+        for (i, case) in test.cases.iter().enumerate() {
+            if let Some(ref subtask_judge) = subtask_judge {
+                if !subtask_judge.needs(i) {
+                    cases.push(skipped_case());
+                    continue;
+                }
+            }
+
+            send_progress(progress, JudgeState::Judgeing, Some(i as u32), &cases);
+
+            let (input, answer) = resolve_case(base_dir, case)?;
+            let mut result = match (&spj, &interactor) {
+                (checker, Some(interactor)) => {
+                    let checker = checker.as_ref();
+                    self.judge_interactive(&user, interactor, checker, &input, &answer, diagnostics)
+                        .await?
+                }
+                (Some(spj), None) => {
+                    self.judge_special(&user, spj, &input, &answer, diagnostics)
+                        .await?
+                }
+                (None, None) => {
+                    self.judge_normal(&user, &input, &answer, diagnostics)
+                        .await?
+                }
+            };
+
+            result.score = Some(score::case_score(&result));
+
+            match subtask_judge {
+                Some(ref mut subtask_judge) => {
+                    subtask_judge.update(i, &result);
+                    cases.push(result);
+                }
+                None => {
+                    let is_accepted = result.kind == JudgeResultKind::Accepted;
+                    cases.push(result);
+
+                    if !is_accepted && matches!(test.policy, TestPolicy::Fuse) {
+                        break;
+                    }
+                }
+            }
+        }
+
+        send_progress(progress, JudgeState::Finished, None, &cases);
+
+        let (subtasks, score) = evaluate_score(&test, &cases);
+        Ok(JudgeResult {
+            cases,
+            extra: None,
+            subtasks,
+            score,
+            discovered_cases,
+        })
+    }
+
+    /// creates the sandbox root of an executable and compiles it
+    async fn prepare(
+        &self,
+        workspace: &Path,
+        files_dir: &Path,
+        root_name: &str,
+        code_name: &str,
+        executable: &Executable,
+        shared_files: &[&str],
+    ) -> Result<Compiled> {
+        let language = &executable.environment.language;
+        if self.disabled_languages.read().unwrap().contains(language) {
+            reject_error!(
+                ErrorCode::NotSupported,
+                Some(format!(
+                    "language is disabled by the self-test: {}",
+                    language
+                ))
+            );
+        }
+        let lang: Arc<dyn Language> = lang::from_environment(&executable.environment)?.into();
+
+        let root = workspace.join(root_name);
+        fs::create_dir(&root)?;
+        fs::copy(files_dir.join(code_name), root.join(lang.src_name()))?;
+        for name in shared_files {
+            fs::copy(files_dir.join(name), root.join(name))?;
+        }
+        self.chown_all(&root)?;
+
+        let runtime = &executable.limit.runtime;
+        let limit = self.effective_limit(Limit {
+            real_time: runtime.cpu_time.saturating_mul(2),
+            cpu_time: runtime.cpu_time,
+            memory: runtime.memory,
+            output: runtime.output,
+            pids: self.hard_limit.pids,
+        });
+
+        if lang.needs_compile() {
+            let compiler = &executable.limit.compiler;
+            let compile_limit = self.effective_limit(Limit {
+                real_time: compiler.cpu_time.saturating_mul(2),
+                cpu_time: compiler.cpu_time,
+                memory: compiler.memory,
+                output: compiler.output,
+                pids: self.hard_limit.pids,
+            });
+
+            let output = {
+                let lang = lang.clone();
+                let sandbox = self.sandbox.clone();
+                let root = root.clone();
+                let compile_limit = compile_limit.clone();
+                task::spawn_blocking(move || lang.compile(&*sandbox, root, &compile_limit))
+                    .await??
+            };
+
+            if !output.is_success() {
+                let kind = verdict::compile_verdict(&output, &compile_limit);
+                let message = read_message(&root.join(lang.msg_name()), compiler.message);
+                return Ok(Compiled::Failed { kind, message });
+            }
+        }
+
+        Ok(Compiled::Ok(Program {
+            lang,
+            sandbox: self.sandbox.clone(),
+            root,
+            limit,
+        }))
+    }
+
+    /// runs the user program with the input file, returns the output and the verdict
+    async fn run_user(
+        &self,
+        user: &Program,
+        input: &Path,
+    ) -> Result<(SandboxOutput, Option<JudgeResultKind>)> {
+        fs::copy(input, user.root.join("__input"))?;
+        let output = run_program(user, &[], "__input", "__user_out", "__user_err").await?;
+        let kind = verdict::runtime_verdict(&output, &user.limit);
+        Ok((output, kind))
+    }
+
+    async fn judge_normal(
+        &self,
+        user: &Program,
+        input: &Path,
+        answer: &Path,
+        diagnostics: bool,
+    ) -> Result<JudgeCaseResult> {
+        let (output, kind) = self.run_user(user, input).await?;
+        let mut difference = None;
+        let kind = match kind {
+            Some(kind) => kind,
+            None => {
+                let user_out = fs::read(user.root.join("__user_out"))?;
+                let answer = fs::read(answer)?;
+                match verdict::first_difference(&user_out, &answer) {
+                    None => JudgeResultKind::Accepted,
+                    Some((line, u, a)) => {
+                        let limit = self.diagnostics.difference.as_u64();
+                        difference = Some(OutputDifference {
+                            line,
+                            user: excerpt(u, limit),
+                            answer: excerpt(a, limit),
+                        });
+                        JudgeResultKind::WrongAnswer
+                    }
+                }
+            }
+        };
+
+        let mut result = to_case_result(kind, &output);
+        if diagnostics && kind != JudgeResultKind::Accepted {
+            result.diagnostics = Some(self.diagnose(user, &output, true, difference));
+        }
+        Ok(result)
+    }
+
+    async fn judge_special(
+        &self,
+        user: &Program,
+        spj: &Program,
+        input: &Path,
+        answer: &Path,
+        diagnostics: bool,
+    ) -> Result<JudgeCaseResult> {
+        let (output, kind) = self.run_user(user, input).await?;
+        if let Some(kind) = kind {
+            let mut result = to_case_result(kind, &output);
+            if diagnostics {
+                result.diagnostics = Some(self.diagnose(user, &output, true, None));
+            }
+            return Ok(result);
+        }
+
+        let user_out = user.root.join("__user_out");
+        let (kind, score, message) = self.run_checker(spj, input, &user_out, answer).await?;
+
+        let message = Some(message).filter(|m| !m.is_empty());
+        let diagnostics = if diagnostics && kind != JudgeResultKind::Accepted {
+            Some(self.diagnose(user, &output, true, None))
+        } else {
+            None
+        };
+        Ok(JudgeCaseResult {
+            score,
+            message,
+            diagnostics,
+            ..to_case_result(kind, &output)
+        })
+    }
+
+    /// runs a testlib checker: `<input> <output> <answer>`
+    async fn run_checker(
+        &self,
+        checker: &Program,
+        input: &Path,
+        output: &Path,
+        answer: &Path,
+    ) -> Result<(JudgeResultKind, Option<f64>, String)> {
+        fs::copy(input, checker.root.join("__input"))?;
+        fs::copy(output, checker.root.join("__user_out"))?;
+        fs::copy(answer, checker.root.join("__answer"))?;
+        let args = ["__input", "__user_out", "__answer"];
+        let checker_output =
+            run_program(checker, &args, "/dev/null", "__spj_out", "__spj_err").await?;
+
+        let message_limit = self.diagnostics.message.as_u64();
+        let message = read_message(&checker.root.join("__spj_err"), message_limit);
+        let message = message.unwrap_or_default();
+        let (kind, score) = verdict::checker_verdict(&checker_output, &checker.limit, &message);
+        Ok((kind, score, message))
+    }
+
+    async fn judge_interactive(
+        &self,
+        user: &Program,
+        interactor: &Program,
+        checker: Option<&Program>,
+        input: &Path,
+        answer: &Path,
+        diagnostics: bool,
+    ) -> Result<JudgeCaseResult> {
+        // the pipes are shared by hard links
+        let pipes = ["__pipe_in", "__pipe_out"];
+        for pipe in &pipes {
+            let user_pipe = user.root.join(pipe);
+            let interactor_pipe = interactor.root.join(pipe);
+            for path in &[&user_pipe, &interactor_pipe] {
+                if path.exists() {
+                    fs::remove_file(path)?;
+                }
+            }
+            unistd::mkfifo(&user_pipe, Mode::from_bits_truncate(0o600))?;
+            self.chown(&user_pipe)?;
+            fs::hard_link(&user_pipe, &interactor_pipe)?;
+        }
+
+        // testlib interactor: <input> <output> [<answer>]
+        fs::copy(input, interactor.root.join("__input"))?;
+        fs::copy(answer, interactor.root.join("__answer"))?;
+        self.chown_all(&interactor.root)?;
+
+        // keeps both ends of pipes open so that opening a pipe never blocks
+        let holders = pipes
+            .iter()
+            .map(|pipe| {
+                fs::OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .open(user.root.join(pipe))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let user_task = run_program(user, &[], "__pipe_in", "__pipe_out", "__user_err");
+        let interactor_task = run_program(
+            interactor,
+            &["__input", "__interactor_out", "__answer"],
+            "__pipe_out",
+            "__pipe_in",
+            "__interactor_err",
+        );
+        futures::pin_mut!(user_task);
+        futures::pin_mut!(interactor_task);
+
+        // closes the pipes when either side exits, so that the other side can see EOF
+        let (user_output, interactor_output) =
+            match future::select(user_task, interactor_task).await {
+                Either::Left((user_output, interactor_task)) => {
+                    drop(holders);
+                    (user_output?, interactor_task.await?)
+                }
+                Either::Right((interactor_output, user_task)) => {
+                    drop(holders);
+                    (user_task.await?, interactor_output?)
+                }
+            };
+
+        let message = read_message(
+            &interactor.root.join("__interactor_err"),
+            self.diagnostics.message.as_u64(),
+        );
+        let message = message.unwrap_or_default();
+
+        let user_kind = verdict::runtime_verdict(&user_output, &user.limit);
+        let (interactor_kind, score) =
+            verdict::checker_verdict(&interactor_output, &interactor.limit, &message);
+
+        let (kind, score) = match (user_kind, interactor_kind) {
+            (Some(kind @ JudgeResultKind::TimeLimitExceeded), _)
+            | (Some(kind @ JudgeResultKind::MemoryLimitExceeded), _)
+            | (Some(kind @ JudgeResultKind::OutputLimitExceeded), _) => (kind, None),
+            (_, kind) if kind != JudgeResultKind::Accepted => (kind, score),
+            (Some(kind), _) => (kind, None),
+            (None, kind) => (kind, score),
+        };
+
+        // the interactor accepts the interaction, then the checker judges its output
+        let (kind, score, message) = match checker {
+            Some(checker) if kind == JudgeResultKind::Accepted => {
+                let interactor_out = interactor.root.join("__interactor_out");
+                self.run_checker(checker, input, &interactor_out, answer)
+                    .await?
+            }
+            _ => (kind, score, message),
+        };
+
+        let message = Some(message).filter(|m| !m.is_empty());
+        let diagnostics = if diagnostics && kind != JudgeResultKind::Accepted {
+            Some(self.diagnose(user, &user_output, false, None))
+        } else {
+            None
+        };
+        Ok(JudgeCaseResult {
+            score,
+            message,
+            diagnostics,
+            ..to_case_result(kind, &user_output)
+        })
+    }
+
+    /// collects diagnostics of the user program
+    fn diagnose(
+        &self,
+        user: &Program,
+        output: &SandboxOutput,
+        has_stdout: bool,
+        difference: Option<OutputDifference>,
+    ) -> CaseDiagnostics {
+        let limit = self.diagnostics.output.as_u64();
+        let stdout = if has_stdout {
+            read_message(&user.root.join("__user_out"), limit)
+        } else {
+            None
+        };
+        CaseDiagnostics {
+            exit_code: Some(output.code),
+            signal: Some(output.signal).filter(|&signal| signal != 0),
+            real_time: Some(output.real_time),
+            stdout,
+            stderr: read_message(&user.root.join("__user_err"), limit),
+            difference,
+        }
+    }
+
+    fn effective_limit(&self, limit: Limit) -> Limit {
+        let hard_limit = &self.hard_limit;
+        Limit {
+            real_time: limit.real_time.min(hard_limit.real_time),
+            cpu_time: limit.cpu_time.min(hard_limit.cpu_time),
+            memory: limit.memory.min(hard_limit.memory),
+            output: limit.output.min(hard_limit.output),
+            pids: limit.pids.min(hard_limit.pids),
+        }
+    }
+
+    fn chown(&self, path: &Path) -> Result<()> {
+        let uid = Some(Uid::from_raw(self.uid));
+        let gid = Some(Gid::from_raw(self.gid));
+        unistd::chown(path, uid, gid)
+            .with_context(|| format!("failed to chown: path = {}", path.display()))
+    }
+
+    /// chowns the directory and its direct children
+    fn chown_all(&self, dir: &Path) -> Result<()> {
+        self.chown(dir)?;
+        for entry in fs::read_dir(dir)? {
+            self.chown(&entry?.path())?;
+        }
+        Ok(())
+    }
 
-        match judge {
-            Judge::Normal { user } => {}
-            Judge::Special { user, spj } => {}
-            Judge::Interactive { user, interactor } => {}
+    /// removes workspaces of stopped judges which could not be removed before
+    ///
+    /// Workspaces of running judges and of other processes are left alone.
+    pub fn clean_workspaces(&self) {
+        let workspaces = mem::take(&mut *self.leftover_workspaces.lock().unwrap());
+        for workspace in workspaces {
+            if let Err(err) = fs::remove_dir_all(&workspace) {
+                warn!(%err, workspace = %workspace.display(), "failed to remove workspace");
+            }
         }
+    }
 
-        todo!()
+    fn remove_workspace(&self, workspace: PathBuf) {
+        if let Err(err) = fs::remove_dir_all(&workspace) {
+            warn!(%err, workspace = %workspace.display(), "failed to remove workspace");
+            self.leftover_workspaces.lock().unwrap().push(workspace);
+        }
     }
 
     fn create_workspace(&self, name: &str) -> Result<PathBuf> {
@@ -165,7 +816,8 @@ impl ExecutorModule {
             Judge::Interactive {
                 ref user,
                 interactor,
-            } => (user, None, Some(interactor)),
+                checker,
+            } => (user, checker.as_ref(), Some(interactor)),
         };
 
         auto_join(|j| {
@@ -189,3 +841,352 @@ impl ExecutorModule {
         Ok(())
     }
 }
+
+fn send_progress(
+    progress: &watch::Sender<Progress>,
+    state: JudgeState,
+    current_case: Option<u32>,
+    cases: &[JudgeCaseResult],
+) {
+    // the receiver may be dropped if progress is not needed
+    let _ = progress.send(Progress {
+        state,
+        current_case,
+        cases: cases.to_vec(),
+    });
+}
+
+async fn run_program(
+    program: &Program,
+    args: &[&'static str],
+    stdin: &str,
+    stdout: &str,
+    stderr: &str,
+) -> Result<SandboxOutput> {
+    let lang = program.lang.clone();
+    let sandbox = program.sandbox.clone();
+    let root = program.root.clone();
+    let limit = program.limit.clone();
+    let args = args.to_vec();
+    let (stdin, stdout, stderr) = (stdin.into(), stdout.into(), stderr.into());
+    task::spawn_blocking(move || {
+        lang.run_with_args(&*sandbox, root, &args, stdin, stdout, stderr, &limit)
+    })
+    .await?
+}
+
+/// resolves the paths of a test case, which must stay inside `base_dir`
+fn resolve_case(base_dir: &Path, case: &TestCase) -> Result<(PathBuf, PathBuf)> {
+    fn resolve(base_dir: &Path, name: &str) -> Result<PathBuf> {
+        let path = Path::new(name);
+        let is_valid = !name.is_empty()
+            && path
+                .components()
+                .all(|c| matches!(c, Component::Normal(_) | Component::CurDir));
+        if !is_valid {
+            reject_error!(
+                ErrorCode::InvalidRequest,
+                Some(format!("invalid test case path: {}", name))
+            )
+        }
+        Ok(base_dir.join(path))
+    }
+
+    Ok((
+        resolve(base_dir, &case.input)?,
+        resolve(base_dir, &case.output)?,
+    ))
+}
+
+/// reads at most `limit` bytes of a message
+fn read_message(path: &Path, limit: u64) -> Option<String> {
+    let file = fs::File::open(path).ok()?;
+    let mut content = Vec::new();
+    file.take(limit).read_to_end(&mut content).ok()?;
+    Some(String::from_utf8_lossy(&content).into_owned())
+}
+
+fn excerpt(bytes: &[u8], limit: u64) -> String {
+    let len = bytes.len().min(limit as usize);
+    String::from_utf8_lossy(&bytes[..len]).into_owned()
+}
+
+fn to_case_result(kind: JudgeResultKind, output: &SandboxOutput) -> JudgeCaseResult {
+    JudgeCaseResult {
+        kind,
+        time: verdict::cpu_time(output),
+        memory: verdict::memory(output),
+        score: None,
+        message: None,
+        diagnostics: None,
+    }
+}
+
+fn skipped_case() -> JudgeCaseResult {
+    JudgeCaseResult {
+        kind: JudgeResultKind::Skipped,
+        time: 0,
+        memory: 0,
+        score: None,
+        message: None,
+        diagnostics: None,
+    }
+}
+
+fn evaluate_score(
+    test: &Test,
+    cases: &[JudgeCaseResult],
+) -> (Option<Vec<SubtaskResult>>, Option<f64>) {
+    match test.subtasks {
+        Some(ref subtasks) => {
+            let (results, total) = score::evaluate(subtasks, cases);
+            (Some(results), Some(total))
+        }
+        None => (None, Some(score::aggregate(cases, test.cases.len()))),
+    }
+}
+
+/// all cases fail with the same kind
+fn failed_result(
+    test: &Test,
+    discovered_cases: Option<Vec<TestCase>>,
+    kind: JudgeResultKind,
+    user: Option<ExecutionInfo>,
+    spj: Option<ExecutionInfo>,
+    interactive: Option<ExecutionInfo>,
+) -> JudgeResult {
+    let case = JudgeCaseResult {
+        kind,
+        time: 0,
+        memory: 0,
+        score: None,
+        message: None,
+        diagnostics: None,
+    };
+    let cases = vec![case; test.cases.len()];
+    let (subtasks, score) = evaluate_score(test, &cases);
+    JudgeResult {
+        cases,
+        extra: Some(JudgeResultExtra {
+            user,
+            spj,
+            interactive,
+        }),
+        subtasks,
+        score,
+        discovered_cases,
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::sandbox::mock::{MockRun, MockSandbox};
+
+    use heng_protocol::common::{
+        CompilerLimit, Environment, File, Limit as ExecutableLimit, RuntimeLimit,
+    };
+    use heng_utils::container::{inject, Container};
+    use heng_utils::temp_dir::TempDir;
+
+    use std::sync::Once;
+
+    use nix::sys::signal::Signal;
+    use serde_json::Map;
+
+    const MIB: u64 = 1 << 20;
+
+    /// creates an executor whose directories are temporary and owned by the current user
+    pub(crate) fn executor(sandbox: MockSandbox) -> (TempDir, Config, ExecutorModule) {
+        // the language configs are injected
+        static INIT: Once = Once::new();
+        INIT.call_once(|| {
+            let config = Config::from_file("heng-judger.toml").unwrap();
+            let mut container = Container::new();
+            container.register(Arc::new(config));
+            container.install_global();
+        });
+
+        let dir = TempDir::new("heng-exec").unwrap();
+        let mut config = (*inject::<Config>()).clone();
+        config.data.directory = dir.join("data");
+        config.executor.workspace_root = dir.join("workspace");
+        config.executor.uid = unistd::getuid().as_raw();
+        config.executor.gid = unistd::getgid().as_raw();
+
+        let data_module = Arc::new(DataModule::new(&config).unwrap());
+        let executor = ExecutorModule::new(&config, data_module, Arc::new(sandbox)).unwrap();
+        (dir, config, executor)
+    }
+
+    fn direct(content: &str) -> File {
+        File::Direct {
+            content: content.to_owned(),
+            hashsum: None,
+            base64: false,
+        }
+    }
+
+    /// a normal judge whose cases are `(input, answer)`
+    fn judge_args(id: &str, language: &str, cases: &[(&str, &str)]) -> CreateJudgeArgs {
+        let mut dynamic_files = Vec::new();
+        let mut test_cases = Vec::new();
+        for (i, (input, answer)) in cases.iter().enumerate() {
+            let (input_name, answer_name) = (format!("{}.in", i), format!("{}.ans", i));
+            dynamic_files.push(DynamicFile::Remote {
+                name: input_name.clone(),
+                file: direct(input),
+            });
+            dynamic_files.push(DynamicFile::Remote {
+                name: answer_name.clone(),
+                file: direct(answer),
+            });
+            test_cases.push(TestCase {
+                input: input_name,
+                output: answer_name,
+            });
+        }
+
+        let user = Executable {
+            source: direct("mock"),
+            environment: Environment {
+                language: language.to_owned(),
+                system: std::env::consts::OS.to_owned(),
+                arch: std::env::consts::ARCH.to_owned(),
+                options: Map::new(),
+            },
+            limit: ExecutableLimit {
+                runtime: RuntimeLimit {
+                    memory: 64 * MIB,
+                    cpu_time: 1000,
+                    output: MIB,
+                },
+                compiler: CompilerLimit {
+                    memory: 256 * MIB,
+                    cpu_time: 5000,
+                    output: 16 * MIB,
+                    message: 1024,
+                },
+            },
+        };
+
+        CreateJudgeArgs {
+            id: id.to_owned(),
+            data: None,
+            dynamic_files: Some(dynamic_files),
+            judge: Judge::Normal { user },
+            test: Test {
+                cases: test_cases,
+                policy: TestPolicy::All,
+                subtasks: None,
+                discover: false,
+            },
+            diagnostics: false,
+        }
+    }
+
+    async fn exec(executor: &ExecutorModule, args: CreateJudgeArgs) -> JudgeResult {
+        let (progress_tx, _progress_rx) = watch::channel(Progress {
+            state: JudgeState::Pending,
+            current_case: None,
+            cases: Vec::new(),
+        });
+        executor.exec(args, &progress_tx).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn normal() {
+        // an echo program
+        let (_dir, _, executor) = executor(MockSandbox::new(|call| {
+            Ok(MockRun::exit(0).stdout(call.stdin()?).cpu_time(10))
+        }));
+        let args = judge_args(
+            "__test_exec_normal",
+            "python",
+            &[("1 2", "1 2\n"), ("3", "4")],
+        );
+        let result = exec(&executor, args).await;
+
+        let kinds: Vec<_> = result.cases.iter().map(|c| c.kind).collect();
+        assert_eq!(
+            kinds,
+            [JudgeResultKind::Accepted, JudgeResultKind::WrongAnswer]
+        );
+        assert_eq!(result.cases[0].time, 10);
+    }
+
+    #[tokio::test]
+    async fn limits() {
+        let (_dir, _, executor) = executor(MockSandbox::new(|call| {
+            let run = match &*call.stdin()? {
+                b"tle" => MockRun::exit(0).cpu_time(2000),
+                b"idle" => MockRun::exit(0).real_time(5000),
+                b"mle" => MockRun::exit(0).memory(128 * MIB),
+                b"ole" => MockRun::exit(0).stdout(vec![b'a'; 2 * MIB as usize]),
+                b"re" => MockRun::exit(1),
+                _ => MockRun::killed(Signal::SIGSEGV),
+            };
+            Ok(run)
+        }));
+        let cases = ["tle", "idle", "mle", "ole", "re", "segv"];
+        let cases: Vec<_> = cases.iter().map(|input| (*input, "")).collect();
+        let result = exec(
+            &executor,
+            judge_args("__test_exec_limits", "python", &cases),
+        )
+        .await;
+
+        let kinds: Vec<_> = result.cases.iter().map(|c| c.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                JudgeResultKind::TimeLimitExceeded,
+                JudgeResultKind::TimeLimitExceeded,
+                JudgeResultKind::MemoryLimitExceeded,
+                JudgeResultKind::OutputLimitExceeded,
+                JudgeResultKind::RuntimeError,
+                JudgeResultKind::RuntimeError,
+            ]
+        );
+        assert_eq!(result.cases[2].memory, 64 * MIB);
+    }
+
+    #[tokio::test]
+    async fn clean_own_workspaces() {
+        let (dir, _, executor) = executor(MockSandbox::new(|call| {
+            Ok(MockRun::exit(0).stdout(call.stdin()?))
+        }));
+        let foreign = dir.join("workspace").join("__other_judger");
+        fs::create_dir(&foreign).unwrap();
+
+        let args = judge_args("__test_exec_clean", "python", &[("1", "1")]);
+        exec(&executor, args).await;
+        executor.clean_workspaces();
+
+        let entries: Vec<_> = fs::read_dir(dir.join("workspace"))
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        assert_eq!(entries, [foreign]);
+    }
+
+    #[tokio::test]
+    async fn compile_error() {
+        let sandbox = MockSandbox::new(|call| {
+            if call.args().contains(&"-o") {
+                return Ok(MockRun::exit(1).stderr("src.cpp:1:1: error: expected ';'"));
+            }
+            panic!("unexpected run");
+        });
+        let (_dir, _, executor) = executor(sandbox);
+        let args = judge_args("__test_exec_compile_error", "cpp", &[("", "")]);
+        let result = exec(&executor, args).await;
+
+        assert_eq!(result.cases[0].kind, JudgeResultKind::CompileError);
+        let message = result
+            .extra
+            .and_then(|extra| extra.user)
+            .and_then(|user| user.compile_message);
+        assert_eq!(message.as_deref(), Some("src.cpp:1:1: error: expected ';'"));
+    }
+}
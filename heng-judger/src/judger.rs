@@ -1,18 +1,26 @@
 use crate::config::Config;
+use crate::data::DataModule;
+use crate::exec::{ExecutorModule, Progress};
+use crate::selftest;
+use crate::status::StatusCollector;
 use crate::{WsMessage, WsStream};
 
 use heng_utils::container::inject;
 
-use heng_protocol::common::JudgeResult;
+use heng_protocol::common::{
+    JudgeCaseResult, JudgeResult, JudgeResultKind, JudgeStatus, StatusReport,
+};
 use heng_protocol::error::ErrorCode;
-use heng_protocol::internal::{ConnectionSettings, ErrorInfo, PartialConnectionSettings};
+use heng_protocol::internal::codec::{self, Codec, Frame};
+use heng_protocol::internal::rpc::{RpcConfig, RpcError, RpcSession};
+use heng_protocol::internal::{ConnectionSettings, Feature, JudgeState, PartialConnectionSettings};
 
 use heng_protocol::internal::ws_json::{
     CreateJudgeArgs, FinishJudgeArgs, Message as RpcMessage, ReportStatusArgs,
     Request as RpcRequest, Response as RpcResponse, UpdateJudgeArgs,
 };
 
-use std::sync::atomic::{AtomicU32, AtomicU64, Ordering::Relaxed};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering::Relaxed};
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -23,8 +31,7 @@ use futures::stream::SplitStream;
 use futures::StreamExt;
 use futures::TryFutureExt;
 use serde::Serialize;
-use serde_json::value::RawValue;
-use tokio::sync::{mpsc, oneshot, Mutex};
+use tokio::sync::{mpsc, watch, Notify, Semaphore};
 use tokio::{task, time};
 use tokio_stream::wrappers::ReceiverStream;
 use tokio_tungstenite::tungstenite;
@@ -32,34 +39,87 @@ use tracing::{debug, error, info, warn};
 use tungstenite::protocol::frame::coding::CloseCode;
 use tungstenite::protocol::CloseFrame;
 
+/// features implemented by the judger
+pub const FEATURES: &[Feature] = &[
+    Feature::StatusReport,
+    Feature::MessagePack,
+    Feature::JudgeProgress,
+    Feature::Drain,
+];
+
 pub struct Judger {
     settings: Settings,
-    counter: Mutex<Counter>,
-    session: WsSession,
-    rpc_timeout: u64,
-}
-
-struct WsSession {
-    sender: mpsc::Sender<WsMessage>,
-    seq: AtomicU32,
-    callbacks: DashMap<u32, oneshot::Sender<RpcResponse>>,
+    judges: DashMap<Arc<str>, watch::Receiver<Progress>>,
+    finished: AtomicU32,
+    /// judges which are not reported yet
+    running: AtomicU32,
+    idle: Notify,
+    /// no more judges are accepted
+    draining: AtomicBool,
+    /// judges beyond `max_concurrency` wait for a permit
+    permits: Semaphore,
+    /// a self-test requested by the controller is running
+    self_testing: AtomicBool,
+    status_collector: StatusCollector,
+    /// the current connection, which is replaced on reconnect
+    conn_tx: watch::Sender<Option<Arc<Connection>>>,
+    conn_rx: watch::Receiver<Option<Arc<Connection>>>,
 }
 
 struct Settings {
     status_report_interval: AtomicU64,
+    progress_interval: Duration,
+    rpc_config: RpcConfig,
+}
+
+/// a ws session to the controller
+///
+/// Judges outlive connections. Their results are reported by the next connection.
+struct Connection {
+    features: Vec<Feature>,
+    session: Arc<RpcSession>,
+    ws_sender: mpsc::Sender<WsMessage>,
 }
 
 #[derive(Debug, Clone)]
 struct Counter {
-    pending: u64,
-    judging: u64,
-    finished: u64,
+    pending: u32,
+    preparing: u32,
+    judging: u32,
+    finished: u32,
 }
 
 impl Judger {
-    pub async fn run(ws_stream: WsStream) -> Result<()> {
-        let config = inject::<Config>();
+    pub fn new(config: &Config, max_concurrency: u32) -> Arc<Self> {
+        let (conn_tx, conn_rx) = watch::channel(None);
+        Arc::new(Self {
+            settings: Settings {
+                status_report_interval: AtomicU64::new(1000),
+                progress_interval: Duration::from_millis(config.judger.progress_interval),
+                rpc_config: RpcConfig {
+                    timeout: Duration::from_millis(config.judger.rpc_timeout),
+                    max_inflight: config.judger.rpc_max_inflight,
+                },
+            },
+            judges: DashMap::new(),
+            finished: AtomicU32::new(0),
+            running: AtomicU32::new(0),
+            idle: Notify::new(),
+            draining: AtomicBool::new(false),
+            permits: Semaphore::new(max_concurrency as usize),
+            self_testing: AtomicBool::new(false),
+            status_collector: StatusCollector::new(),
+            conn_tx,
+            conn_rx,
+        })
+    }
 
+    /// serves a connection until it is closed
+    pub async fn serve(
+        self: &Arc<Self>,
+        ws_stream: WsStream,
+        features: Vec<Feature>,
+    ) -> Result<()> {
         let (ws_sink, ws_stream) = ws_stream.split();
         let (tx, rx) = mpsc::channel::<WsMessage>(4096);
 
@@ -70,30 +130,61 @@ impl Judger {
                 .inspect_err(|err| error!(%err, "ws forward error")),
         );
 
-        let judger = Arc::new(Self {
-            settings: Settings {
-                status_report_interval: AtomicU64::new(1000),
-            },
-            session: WsSession {
-                sender: tx,
-                seq: AtomicU32::new(0),
-                callbacks: DashMap::new(),
-            },
-            counter: Mutex::new(Counter {
-                pending: 0,
-                judging: 0,
-                finished: 0,
-            }),
-            rpc_timeout: config.judger.rpc_timeout,
+        let (rpc_tx, rpc_rx) = mpsc::channel::<RpcMessage>(4096);
+        {
+            let ws_tx = tx.clone();
+            let codec = Codec::negotiate(&features);
+            task::spawn(async move {
+                let mut rpc_rx = ReceiverStream::new(rpc_rx);
+                while let Some(rpc_msg) = rpc_rx.next().await {
+                    let ws_msg = match codec.encode(&rpc_msg) {
+                        Ok(Frame::Text(text)) => WsMessage::Text(text),
+                        Ok(Frame::Binary(bytes)) => WsMessage::Binary(bytes),
+                        Err(err) => {
+                            error!(%err, "failed to encode rpc message");
+                            continue;
+                        }
+                    };
+                    if ws_tx.send(ws_msg).await.is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+
+        let conn = Arc::new(Connection {
+            features,
+            session: Arc::new(RpcSession::new(rpc_tx, self.settings.rpc_config.clone())),
+            ws_sender: tx,
         });
+        let _ = self.conn_tx.send(Some(conn.clone()));
+
+        task::spawn(self.clone().report_status_loop(conn.clone()));
 
-        task::spawn(judger.clone().report_status_loop());
+        // a new session does not know that the judger is draining
+        if self.draining.load(Relaxed) {
+            task::spawn(drain(conn.clone()));
+        }
 
-        judger.main_loop(ws_stream).await
+        let result = self.clone().main_loop(&conn, ws_stream).await;
+        conn.session.close();
+        let _ = self.conn_tx.send(None);
+        result
     }
 
-    async fn main_loop(self: Arc<Self>, mut ws_stream: SplitStream<WsStream>) -> Result<()> {
+    async fn main_loop(
+        self: Arc<Self>,
+        conn: &Arc<Connection>,
+        mut ws_stream: SplitStream<WsStream>,
+    ) -> Result<()> {
         info!("starting main loop");
+
+        let handler = {
+            let this = self.clone();
+            let conn = conn.clone();
+            move |req| this.clone().handle_rpc_request(conn.clone(), req)
+        };
+
         while let Some(frame) = ws_stream.next().await {
             use tungstenite::Message::*;
 
@@ -104,44 +195,25 @@ impl Judger {
                     warn!(?reason, "ws session closed");
                     return Ok(());
                 }
-                Text(text) => {
-                    let rpc_msg: RpcMessage = match serde_json::from_str(&text) {
+                Text(_) | Binary(_) => {
+                    let result = match frame {
+                        Text(ref text) => codec::decode_text(text),
+                        Binary(ref bytes) => codec::decode_binary(bytes),
+                        _ => unreachable!(),
+                    };
+                    let rpc_msg: RpcMessage = match result {
                         Ok(m) => m,
                         Err(err) => {
-                            error!(%err, "internal protocol: message format error:\n{:?}\n",text);
+                            error!(%err, "internal protocol: message format error:\n{:?}\n", frame);
                             let close_frame = CloseFrame {
                                 code: CloseCode::Invalid,
                                 reason: "internal protocol message format error".into(),
                             };
-                            let _ = self.session.sender.send(Close(Some(close_frame))).await;
+                            let _ = conn.ws_sender.send(Close(Some(close_frame))).await;
                             return Err(err.into());
                         }
                     };
-                    match rpc_msg {
-                        RpcMessage::Request { seq, body, .. } => {
-                            let this = self.clone();
-                            task::spawn(async move {
-                                let response = this.clone().handle_rpc_request(body).await;
-                                let rpc_msg = RpcMessage::Response {
-                                    seq,
-                                    time: Utc::now(),
-                                    body: response,
-                                };
-                                let ws_msg =
-                                    WsMessage::text(serde_json::to_string(&rpc_msg).unwrap());
-                                let _ = this.session.sender.send(ws_msg).await;
-                            });
-                        }
-                        RpcMessage::Response { seq, body, .. } => {
-                            match self.session.callbacks.remove(&seq) {
-                                None => warn!(?seq, "no such callback"),
-                                Some((_, cb)) => match cb.send(body) {
-                                    Ok(()) => {}
-                                    Err(_) => warn!(?seq, "the callback is timeouted"),
-                                },
-                            }
-                        }
-                    }
+                    conn.session.dispatch(rpc_msg, &handler);
                 }
                 _ => {
                     warn!("drop ws message");
@@ -153,75 +225,128 @@ impl Judger {
         Ok(())
     }
 
-    async fn report_status_loop(self: Arc<Self>) -> Result<()> {
+    /// stops accepting judges and waits for running ones to be reported
+    ///
+    /// The connection is closed after all judges are reported or `timeout` elapses.
+    pub async fn shutdown(&self, timeout: Duration) {
+        self.draining.store(true, Relaxed);
+        if let Some(conn) = self.connection() {
+            drain(conn).await;
+        }
+
+        let running = self.running.load(Relaxed);
+        info!(running, "waiting for running judges");
+        if time::timeout(timeout, self.wait_idle()).await.is_err() {
+            let running = self.running.load(Relaxed);
+            warn!(running, "shutdown timeout, abandon running judges");
+        }
+
+        if let Some(conn) = self.connection() {
+            close(&conn, CloseCode::Away, "judger is shutting down").await;
+        }
+    }
+
+    async fn wait_idle(&self) {
+        while self.running.load(Relaxed) > 0 {
+            self.idle.notified().await;
+        }
+    }
+
+    /// the current connection, or `None` while reconnecting
+    fn connection(&self) -> Option<Arc<Connection>> {
+        self.conn_rx
+            .borrow()
+            .clone()
+            .filter(|conn| !conn.session.is_closed())
+    }
+
+    async fn wait_connection(&self) -> Arc<Connection> {
+        let mut rx = self.conn_rx.clone();
         loop {
+            if let Some(conn) = self.connection() {
+                return conn;
+            }
+            // the sender lives as long as `self`
+            let _ = rx.changed().await;
+        }
+    }
+
+    async fn report_status_loop(self: Arc<Self>, conn: Arc<Connection>) -> Result<()> {
+        while !conn.session.is_closed() {
             let delay = self.settings.status_report_interval.load(Relaxed);
             time::sleep(Duration::from_millis(delay)).await;
 
-            let result = self
-                .wsrpc(RpcRequest::ReportStatus(ReportStatusArgs {
+            let cnt = self.count();
+            let report = if !conn.supports(Feature::StatusReport) {
+                None
+            } else {
+                match self.status_collector.collect() {
+                    Ok(hardware) => Some(StatusReport {
+                        hardware,
+                        judge: JudgeStatus {
+                            pending: cnt.pending,
+                            preparing: cnt.preparing,
+                            judging: cnt.judging,
+                            finished: cnt.finished,
+                        },
+                        data: Some(inject::<DataModule>().cache_status()),
+                    }),
+                    Err(err) => {
+                        warn!(%err, "failed to collect hardware status");
+                        None
+                    }
+                }
+            };
+
+            let result = conn
+                .session
+                .request::<()>(RpcRequest::ReportStatus(ReportStatusArgs {
                     collect_time: Utc::now(),
                     next_report_time: Utc::now() + chrono::Duration::milliseconds(delay as i64),
-                    report: None, // FIXME
+                    report,
                 }))
                 .await;
 
-            let cnt = self.count(|cnt| cnt.clone()).await;
-
             match result {
-                Ok(RpcResponse::Output(None)) => {
-                    debug!(interval=?delay, count=?cnt, "report status")
-                }
-                Ok(RpcResponse::Output(Some(value))) => warn!(?value, "unexpected response"),
-                Ok(RpcResponse::Error(err)) => warn!(%err, "report status"),
-                Err(_) => warn!("the request failed"),
+                Ok(()) => debug!(interval=?delay, count=?cnt, "report status"),
+                Err(err) => warn!(%err, "report status"),
             }
         }
+        Ok(())
     }
 
-    async fn handle_rpc_request(self: Arc<Self>, req: RpcRequest) -> RpcResponse {
+    async fn handle_rpc_request(
+        self: Arc<Self>,
+        conn: Arc<Connection>,
+        req: RpcRequest,
+    ) -> RpcResponse {
         match req {
-            RpcRequest::CreateJudge(args) => to_null_response(self.create_judge(args).await),
+            RpcRequest::CreateJudge(args) => to_null_response(self.create_judge(&conn, args).await),
             RpcRequest::Control(args) => to_response(self.control(args).await),
-            _ => RpcResponse::Error(ErrorInfo {
-                code: ErrorCode::NotSupported,
-                message: None,
-            }),
+            _ => RpcResponse::error(ErrorCode::NotSupported, None),
         }
     }
 
-    async fn wsrpc(&self, req: RpcRequest) -> Result<RpcResponse> {
-        let session = &self.session;
-        let seq = session.seq.fetch_add(1, Relaxed).wrapping_add(1);
-        let (tx, rx) = oneshot::channel();
-        let rpc_msg = RpcMessage::Request {
-            seq,
-            time: Utc::now(),
-            body: req,
+    fn count(&self) -> Counter {
+        let mut cnt = Counter {
+            pending: 0,
+            preparing: 0,
+            judging: 0,
+            finished: self.finished.load(Relaxed),
         };
-        let ws_msg = WsMessage::text(serde_json::to_string(&rpc_msg).unwrap());
-
-        {
-            session.callbacks.insert(seq, tx);
-            session.sender.send(ws_msg).await.unwrap();
-        }
-
-        match time::timeout(Duration::from_millis(self.rpc_timeout), rx).await {
-            Ok(res) => Ok(res.unwrap()),
-            Err(err) => {
-                let _ = session.callbacks.remove(&seq);
-                return Err(anyhow::Error::new(err));
+        for entry in self.judges.iter() {
+            match entry.value().borrow().state {
+                JudgeState::Confirmed | JudgeState::Pending => cnt.pending += 1,
+                JudgeState::Preparing => cnt.preparing += 1,
+                JudgeState::Judgeing => cnt.judging += 1,
+                JudgeState::Finished => {}
             }
         }
-    }
-
-    async fn count<T>(&self, f: impl FnOnce(&mut Counter) -> T) -> T {
-        let mut counter = self.counter.lock().await;
-        f(&mut counter)
+        cnt
     }
 
     async fn control(
-        &self,
+        self: &Arc<Self>,
         settings: Option<PartialConnectionSettings>,
     ) -> Result<ConnectionSettings> {
         if let Some(settings) = settings {
@@ -230,6 +355,9 @@ impl Judger {
                     .status_report_interval
                     .store(interval, Relaxed);
             }
+            if settings.self_test {
+                self.spawn_self_test();
+            }
         }
         let current_settings = ConnectionSettings {
             status_report_interval: self.settings.status_report_interval.load(Relaxed),
@@ -237,80 +365,205 @@ impl Judger {
         Ok(current_settings)
     }
 
-    async fn create_judge(self: Arc<Self>, judge: CreateJudgeArgs) -> Result<()> {
+    /// runs the self-test in background
+    ///
+    /// The languages are advertised on login, so the judger logs in again if they change.
+    fn spawn_self_test(self: &Arc<Self>) {
+        if self.self_testing.swap(true, Relaxed) {
+            return;
+        }
+        let this = self.clone();
         task::spawn(async move {
-            self.count(|cnt| cnt.pending += 1).await;
+            let executor = inject::<ExecutorModule>();
+            let languages = executor.languages();
+            if let Err(err) = selftest::apply().await {
+                error!(%err, "failed to run self-test");
+            }
+            this.self_testing.store(false, Relaxed);
 
-            self.count(|cnt| {
-                cnt.pending -= 1;
-                cnt.judging += 1;
-            })
-            .await;
+            let new_languages = executor.languages();
+            if new_languages == languages {
+                return;
+            }
+            info!(languages = ?new_languages, "languages are changed, logging in again");
+            if let Some(conn) = this.connection() {
+                close(&conn, CloseCode::Normal, "languages are changed").await;
+            }
+        });
+    }
 
-            let finish = FinishJudgeArgs {
-                id: judge.id.clone(),
-                result: JudgeResult {
-                    cases: Vec::new(),
-                    extra: None,
-                },
+    async fn create_judge(
+        self: Arc<Self>,
+        conn: &Connection,
+        judge: CreateJudgeArgs,
+    ) -> Result<()> {
+        if self.draining.load(Relaxed) {
+            anyhow::bail!("judger is shutting down");
+        }
+        self.running.fetch_add(1, Relaxed);
+
+        let id: Arc<str> = judge.id.as_str().into();
+
+        let (progress_tx, progress_rx) = watch::channel(Progress {
+            state: JudgeState::Pending,
+            current_case: None,
+            cases: Vec::new(),
+        });
+        self.judges.insert(id.clone(), progress_rx.clone());
+
+        let report_progress = conn.supports(Feature::JudgeProgress);
+        task::spawn(async move {
+            let progress_task = if report_progress {
+                let this = self.clone();
+                Some(task::spawn(this.progress_loop(id.clone(), progress_rx)))
+            } else {
+                None
             };
 
-            self.count(|cnt| {
-                cnt.judging -= 1;
-                cnt.finished += 1;
-            })
-            .await;
+            let case_count = judge.test.cases.len();
+            let executor = inject::<ExecutorModule>();
+            let result = {
+                // the concurrency is enforced locally, whatever the controller dispatches
+                let _permit = self
+                    .permits
+                    .acquire()
+                    .await
+                    .expect("semaphore is never closed");
+                executor.exec(judge, &progress_tx).await
+            };
+
+            // the final result is sent by `finish_judge`
+            if let Some(task) = progress_task {
+                task.abort();
+            }
+
+            let result = result.unwrap_or_else(|err| {
+                error!(%err, %id, "failed to execute judge");
+                JudgeResult {
+                    cases: vec![
+                        JudgeCaseResult {
+                            kind: JudgeResultKind::SystemError,
+                            time: 0,
+                            memory: 0,
+                            score: None,
+                            message: None,
+                            diagnostics: None,
+                        };
+                        case_count
+                    ],
+                    extra: None,
+                    subtasks: None,
+                    score: None,
+                    discovered_cases: None,
+                }
+            });
+
+            self.judges.remove(&*id);
+            self.finished.fetch_add(1, Relaxed);
 
-            self.finish_judge(finish).await
+            let finish = FinishJudgeArgs {
+                id: id.to_string(),
+                result,
+            };
+            if let Err(err) = self.finish_judge(finish).await {
+                error!(%err, %id, "failed to finish judge");
+            }
+
+            if self.running.fetch_sub(1, Relaxed) == 1 {
+                self.idle.notify_one();
+            }
         });
         Ok(())
     }
 
-    async fn update_judge(&self, update: UpdateJudgeArgs) -> Result<()> {
-        let res = self.wsrpc(RpcRequest::UpdateJudge(update)).await?;
-        let output = to_anyhow(res)?;
-        if output.is_some() {
-            warn!(?output, "unexpected output")
+    /// sends the latest progress at most once per `progress_interval`
+    async fn progress_loop(self: Arc<Self>, id: Arc<str>, mut rx: watch::Receiver<Progress>) {
+        while rx.changed().await.is_ok() {
+            let update = {
+                let progress = rx.borrow();
+                UpdateJudgeArgs {
+                    id: id.to_string(),
+                    state: progress.state.clone(),
+                    current_case: progress.current_case,
+                    cases: Some(progress.cases.clone()),
+                }
+            };
+            if let Err(err) = self.update_judge(update).await {
+                warn!(%err, %id, "failed to update judge");
+            }
+            time::sleep(self.settings.progress_interval).await;
         }
+    }
+
+    /// progress is dropped while reconnecting
+    async fn update_judge(&self, update: UpdateJudgeArgs) -> Result<()> {
+        let conn = match self.connection() {
+            Some(conn) if conn.supports(Feature::JudgeProgress) => conn,
+            _ => return Ok(()),
+        };
+        let req = RpcRequest::UpdateJudge(update);
+        conn.session.request::<()>(req).await?;
         Ok(())
     }
 
+    /// sends the result, which is resent by the next connection if the current one is lost
     async fn finish_judge(&self, finish: FinishJudgeArgs) -> Result<()> {
-        let res = self.wsrpc(RpcRequest::FinishJudge(finish)).await?;
-        let output = to_anyhow(res)?;
-        if output.is_some() {
-            warn!(?output, "unexpected output")
+        loop {
+            let conn = self.wait_connection().await;
+            let req = RpcRequest::FinishJudge(finish.clone());
+            match conn.session.request::<()>(req).await {
+                Ok(()) => return Ok(()),
+                // the controller ignores a result which has been received
+                Err(err @ RpcError::Closed) | Err(err @ RpcError::Timeout) => {
+                    warn!(%err, id = %finish.id, "failed to finish judge, retrying");
+                    if !conn.session.is_closed() {
+                        time::sleep(self.settings.progress_interval).await;
+                    }
+                }
+                Err(err) => return Err(err.into()),
+            }
         }
-        Ok(())
     }
 }
 
-fn to_response<T: Serialize>(result: Result<T>) -> RpcResponse {
-    match result {
-        Ok(value) => {
-            let raw_value = RawValue::from_string(serde_json::to_string(&value).unwrap()).unwrap();
-            RpcResponse::Output(Some(raw_value))
-        }
-        Err(err) => RpcResponse::Error(ErrorInfo {
-            code: ErrorCode::UnknownError,
-            message: Some(err.to_string()),
-        }),
+impl Connection {
+    fn supports(&self, feature: Feature) -> bool {
+        self.features.contains(&feature)
     }
 }
 
-fn to_null_response(result: Result<()>) -> RpcResponse {
+async fn close(conn: &Connection, code: CloseCode, reason: &'static str) {
+    conn.session.close();
+    let close_frame = CloseFrame {
+        code,
+        reason: reason.into(),
+    };
+    let _ = conn
+        .ws_sender
+        .send(WsMessage::Close(Some(close_frame)))
+        .await;
+}
+
+/// asks the controller to stop dispatching judges
+async fn drain(conn: Arc<Connection>) {
+    if !conn.supports(Feature::Drain) {
+        return;
+    }
+    if let Err(err) = conn.session.request::<()>(RpcRequest::Drain).await {
+        warn!(%err, "failed to drain");
+    }
+}
+
+fn to_response<T: Serialize>(result: Result<T>) -> RpcResponse {
     match result {
-        Ok(()) => RpcResponse::Output(None),
-        Err(err) => RpcResponse::Error(ErrorInfo {
-            code: ErrorCode::UnknownError,
-            message: Some(err.to_string()),
-        }),
+        Ok(value) => RpcResponse::output(&value),
+        Err(err) => RpcResponse::error(ErrorCode::UnknownError, Some(err.to_string())),
     }
 }
 
-fn to_anyhow(response: RpcResponse) -> Result<Option<Box<RawValue>>> {
-    match response {
-        RpcResponse::Output(output) => Ok(output),
-        RpcResponse::Error(err) => Err(anyhow::Error::from(err)),
+fn to_null_response(result: Result<()>) -> RpcResponse {
+    match result {
+        Ok(()) => RpcResponse::null(),
+        Err(err) => RpcResponse::error(ErrorCode::UnknownError, Some(err.to_string())),
     }
 }
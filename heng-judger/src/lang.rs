@@ -4,37 +4,121 @@ pub mod javascript;
 pub mod python;
 pub mod rust;
 
+use self::c_cpp::{CCpp, CCppStd};
+use self::java::Java;
+use self::javascript::JavaScript;
+use self::python::Python;
+use self::rust::Rust;
+
+use crate::sandbox::{Command, Sandbox, SandboxOutput};
 use crate::Config;
 
-use heng_utils::container::inject;
-use heng_utils::math::roundup_div;
+use heng_protocol::common::Environment;
+use heng_protocol::error::ErrorCode;
+use heng_protocol::internal::ErrorInfo;
 
-use carapace::SandboxOutput;
-use tracing::debug;
+use heng_utils::container::inject;
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use anyhow::Result;
 
-pub trait Language {
+pub trait Language: Send + Sync {
     fn lang_name(&self) -> &str;
 
     fn needs_compile(&self) -> bool;
     fn src_name(&self) -> &str;
     fn msg_name(&self) -> &str;
 
-    fn compile(&self, workspace: PathBuf, hard_limit: &Limit) -> Result<SandboxOutput>;
+    fn compile(
+        &self,
+        sandbox: &dyn Sandbox,
+        workspace: PathBuf,
+        hard_limit: &Limit,
+    ) -> Result<SandboxOutput>;
 
     fn run(
         &self,
+        sandbox: &dyn Sandbox,
         workspace: PathBuf,
         stdin: PathBuf,
         stdout: PathBuf,
         stderr: PathBuf,
         hard_limit: &Limit,
+    ) -> Result<SandboxOutput> {
+        self.run_with_args(sandbox, workspace, &[], stdin, stdout, stderr, hard_limit)
+    }
+
+    fn run_with_args(
+        &self,
+        sandbox: &dyn Sandbox,
+        workspace: PathBuf,
+        args: &[&str],
+        stdin: PathBuf,
+        stdout: PathBuf,
+        stderr: PathBuf,
+        hard_limit: &Limit,
     ) -> Result<SandboxOutput>;
 }
 
+/// names of the supported languages
+pub const LANGUAGES: &[&str] = &["c", "cpp", "java", "javascript", "python", "rust"];
+
+/// selects the language by `environment.language` and `environment.options`
+pub fn from_environment(environment: &Environment) -> Result<Box<dyn Language>> {
+    let options = &environment.options;
+    let o2 = match options.get("o2") {
+        None => true,
+        Some(value) => match value.as_bool() {
+            Some(o2) => o2,
+            None => reject_error!(
+                ErrorCode::InvalidRequest,
+                Some("invalid language option: o2".to_owned())
+            ),
+        },
+    };
+
+    let lang: Box<dyn Language> = match environment.language.as_str() {
+        "c" | "cpp" => {
+            let is_cpp = environment.language == "cpp";
+            let std = match options.get("std") {
+                None => {
+                    if is_cpp {
+                        "cpp17"
+                    } else {
+                        "c11"
+                    }
+                }
+                Some(value) => match value.as_str() {
+                    Some(s) => s,
+                    None => reject_error!(
+                        ErrorCode::InvalidRequest,
+                        Some("invalid language option: std".to_owned())
+                    ),
+                },
+            };
+            let std = match CCppStd::from_str(std) {
+                Ok(std) if std.is_cpp() == is_cpp => std,
+                _ => reject_error!(
+                    ErrorCode::InvalidRequest,
+                    Some(format!("invalid {} std: {}", environment.language, std))
+                ),
+            };
+            Box::new(CCpp { std, o2 })
+        }
+        "java" => Box::new(Java {}),
+        "javascript" => Box::new(JavaScript {}),
+        "python" => Box::new(Python {}),
+        "rust" => Box::new(Rust { o2 }),
+        _ => reject_error!(
+            ErrorCode::NotSupported,
+            Some(format!("unsupported language: {}", environment.language))
+        ),
+    };
+    Ok(lang)
+}
+
+#[derive(Debug, Clone)]
 pub struct Limit {
     pub real_time: u64, // milliseconds
     pub cpu_time: u64,  // milliseconds
@@ -43,68 +127,73 @@ pub struct Limit {
     pub pids: u32,      // number
 }
 
-/// set chroot, uid, gid
-///
-/// set real_time_limit, rlimits, cg_limits
-///
-/// set priority
-///
-/// set mount_proc, mount_tmpfs
-///
-/// set seccomp_forbid_ipc
-///
-/// add env PATH
-///
-/// add some bindmount_rw and bindmount_ro;
-fn sandbox_run(
-    mut cmd: carapace::Command,
+/// clamps the limit by the hard limit of config, then runs the command
+pub fn sandbox_run(
+    sandbox: &dyn Sandbox,
+    cmd: &Command,
     config: &Config,
-    workspace: PathBuf,
+    workspace: &Path,
     hard_limit: &Limit,
 ) -> Result<SandboxOutput> {
     let cfg_hard_limit = &config.executor.hard_limit;
-
-    let real_time = cfg_hard_limit.real_time.min(hard_limit.real_time);
-    let cpu_time = cfg_hard_limit.cpu_time.min(hard_limit.cpu_time);
-    let memory = cfg_hard_limit.memory.as_u64().min(hard_limit.memory);
-    let output = cfg_hard_limit.output.as_u64().min(hard_limit.output);
-    let pids = cfg_hard_limit.pids.min(hard_limit.pids);
-
-    let uid = config.executor.uid;
-    let gid = config.executor.gid;
-
-    let bind_rw = ["/dev/null", "/dev/zero", "/dev/random", "/dev/urandom"];
-    let bind_ro = ["/lib", "/lib64"];
-    let set_bindmount = |s: &[&str], b: Vec<carapace::BindMount>| {
-        let iter1 = s
-            .iter()
-            .map(|src| carapace::BindMount::new_same(src.into()));
-        let iter2 = b.into_iter();
-        iter1.chain(iter2).collect::<Vec<_>>()
+    let limit = Limit {
+        real_time: cfg_hard_limit.real_time.min(hard_limit.real_time),
+        cpu_time: cfg_hard_limit.cpu_time.min(hard_limit.cpu_time),
+        memory: cfg_hard_limit.memory.as_u64().min(hard_limit.memory),
+        output: cfg_hard_limit.output.as_u64().min(hard_limit.output),
+        pids: cfg_hard_limit.pids.min(hard_limit.pids),
     };
+    sandbox.run(cmd, workspace, &limit)
+}
 
-    let c = &mut cmd.config;
-    c.chroot = Some(workspace);
-    c.uid = Some(uid);
-    c.gid = Some(gid);
-    c.real_time_limit = Some(real_time);
-    c.rlimit_cpu = Some(roundup_div(cpu_time, 1000) as u32);
-    c.rlimit_fsize = Some(output);
-    c.cg_limit_memory = Some(memory);
-    c.cg_limit_max_pids = Some(pids);
-    c.priority = Some(-20);
-    c.mount_proc = Some("/proc".into());
-    c.mount_tmpfs = Some("/tmp".into());
-    c.seccomp_forbid_ipc = true;
-
-    c.env.insert(
-        0,
-        "PATH=/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin:.".into(),
-    );
-
-    cmd.config.bindmount_rw = set_bindmount(&bind_rw, cmd.config.bindmount_rw);
-    cmd.config.bindmount_ro = set_bindmount(&bind_ro, cmd.config.bindmount_ro);
-
-    debug!("run embedded carapace:\n{:?}", cmd.config.to_cli_cmd());
-    cmd.run()
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::{json, Value};
+
+    fn environment(language: &str, options: Value) -> Environment {
+        Environment {
+            language: language.to_owned(),
+            system: "Linux".to_owned(),
+            arch: "x64".to_owned(),
+            options: match options {
+                Value::Object(options) => options,
+                _ => panic!("options should be an object"),
+            },
+        }
+    }
+
+    fn error_code(environment: &Environment) -> ErrorCode {
+        let err = from_environment(environment).err().unwrap();
+        err.downcast_ref::<ErrorInfo>().unwrap().code
+    }
+
+    #[test]
+    fn select_language() {
+        let cpp = from_environment(&environment("cpp", json!({}))).unwrap();
+        assert_eq!(cpp.lang_name(), "cpp");
+        assert_eq!(cpp.src_name(), "src.cpp");
+        assert!(cpp.needs_compile());
+
+        let c = from_environment(&environment("c", json!({ "std": "c99", "o2": false }))).unwrap();
+        assert_eq!(c.lang_name(), "c");
+        assert_eq!(c.src_name(), "src.c");
+
+        let python = from_environment(&environment("python", json!({}))).unwrap();
+        assert_eq!(python.lang_name(), "python");
+        assert!(!python.needs_compile());
+    }
+
+    #[test]
+    fn reject_options() {
+        let code = error_code(&environment("c", json!({ "std": "cpp17" })));
+        assert_eq!(code, ErrorCode::InvalidRequest);
+        let code = error_code(&environment("cpp", json!({ "std": 17 })));
+        assert_eq!(code, ErrorCode::InvalidRequest);
+        let code = error_code(&environment("rust", json!({ "o2": "yes" })));
+        assert_eq!(code, ErrorCode::InvalidRequest);
+        let code = error_code(&environment("brainfuck", json!({})));
+        assert_eq!(code, ErrorCode::NotSupported);
+    }
 }
@@ -15,7 +15,7 @@ pub enum CCppStd {
 }
 
 impl CCppStd {
-    fn from_str(s: &str) -> Result<Self> {
+    pub(super) fn from_str(s: &str) -> Result<Self> {
         match s {
             "c89" => Ok(CCppStd::C89),
             "c99" => Ok(CCppStd::C99),
@@ -38,7 +38,7 @@ impl CCppStd {
         }
     }
 
-    fn is_cpp(&self) -> bool {
+    pub(super) fn is_cpp(&self) -> bool {
         matches!(self, CCppStd::Cpp11 | CCppStd::Cpp14 | CCppStd::Cpp17)
     }
 }
@@ -74,7 +74,12 @@ impl Language for CCpp {
         "msg"
     }
 
-    fn compile(&self, workspace: PathBuf, hard_limit: &Limit) -> Result<SandboxOutput> {
+    fn compile(
+        &self,
+        sandbox: &dyn Sandbox,
+        workspace: PathBuf,
+        hard_limit: &Limit,
+    ) -> Result<SandboxOutput> {
         let config = inject::<Config>();
         let c_cpp = &config.executor.c_cpp;
 
@@ -86,7 +91,7 @@ impl Language for CCpp {
             c_cpp.gcc.as_os_str()
         };
 
-        let mut cmd = carapace::Command::new(cc);
+        let mut cmd = Command::new(cc);
 
         cmd.arg("--std").arg(self.std.as_str_gnu());
         cmd.arg("-static");
@@ -105,20 +110,25 @@ impl Language for CCpp {
             cmd.bindmount_ro(mnt, mnt);
         }
 
-        sandbox_run(cmd, &config, workspace, hard_limit)
+        sandbox_run(sandbox, &cmd, &config, &workspace, hard_limit)
     }
 
-    fn run(
+    fn run_with_args(
         &self,
+        sandbox: &dyn Sandbox,
         workspace: PathBuf,
+        args: &[&str],
         stdin: PathBuf,
         stdout: PathBuf,
         stderr: PathBuf,
         hard_limit: &Limit,
     ) -> Result<SandboxOutput> {
         let config = inject::<Config>();
-        let mut cmd = carapace::Command::new(self.exe_name());
+        let mut cmd = Command::new(self.exe_name());
+        for arg in args {
+            cmd.arg(arg);
+        }
         cmd.stdio(stdin, stdout, stderr);
-        sandbox_run(cmd, &config, workspace, hard_limit)
+        sandbox_run(sandbox, &cmd, &config, &workspace, hard_limit)
     }
 }
@@ -19,11 +19,16 @@ impl Language for Java {
         "msg"
     }
 
-    fn compile(&self, workspace: PathBuf, hard_limit: &Limit) -> Result<SandboxOutput> {
+    fn compile(
+        &self,
+        sandbox: &dyn Sandbox,
+        workspace: PathBuf,
+        hard_limit: &Limit,
+    ) -> Result<SandboxOutput> {
         let config = inject::<Config>();
         let java = &config.executor.java;
 
-        let mut cmd = carapace::Command::new(&java.javac);
+        let mut cmd = Command::new(&java.javac);
 
         cmd.arg("-J-Xms64m");
         cmd.arg("-J-Xmx512m");
@@ -39,12 +44,14 @@ impl Language for Java {
             cmd.bindmount_ro(mnt, mnt);
         }
 
-        sandbox_run(cmd, &config, workspace, hard_limit)
+        sandbox_run(sandbox, &cmd, &config, &workspace, hard_limit)
     }
 
-    fn run(
+    fn run_with_args(
         &self,
+        sandbox: &dyn Sandbox,
         workspace: PathBuf,
+        args: &[&str],
         stdin: PathBuf,
         stdout: PathBuf,
         stderr: PathBuf,
@@ -53,11 +60,14 @@ impl Language for Java {
         let config = inject::<Config>();
         let java = &config.executor.java;
 
-        let mut cmd = carapace::Command::new(&java.java);
+        let mut cmd = Command::new(&java.java);
         cmd.arg("-cp").arg(".");
         cmd.arg("-Xms64m");
         cmd.arg("-Xmx512m");
         cmd.arg("Main");
+        for arg in args {
+            cmd.arg(arg);
+        }
         cmd.stdio(stdin, stdout, stderr);
 
         cmd.bindmount_ro(&java.javac, &java.javac);
@@ -65,6 +75,6 @@ impl Language for Java {
             cmd.bindmount_ro(mnt, mnt);
         }
 
-        sandbox_run(cmd, &config, workspace, hard_limit)
+        sandbox_run(sandbox, &cmd, &config, &workspace, hard_limit)
     }
 }
@@ -19,13 +19,15 @@ impl Language for JavaScript {
         unimplemented!()
     }
 
-    fn compile(&self, _: PathBuf, _: &Limit) -> Result<SandboxOutput> {
+    fn compile(&self, _: &dyn Sandbox, _: PathBuf, _: &Limit) -> Result<SandboxOutput> {
         unimplemented!()
     }
 
-    fn run(
+    fn run_with_args(
         &self,
+        sandbox: &dyn Sandbox,
         workspace: PathBuf,
+        args: &[&str],
         stdin: PathBuf,
         stdout: PathBuf,
         stderr: PathBuf,
@@ -34,8 +36,11 @@ impl Language for JavaScript {
         let config = inject::<Config>();
         let js = &config.executor.javascript;
 
-        let mut cmd = carapace::Command::new(&js.node);
+        let mut cmd = Command::new(&js.node);
         cmd.arg(self.src_name());
+        for arg in args {
+            cmd.arg(arg);
+        }
         cmd.stdio(stdin, stdout, stderr);
 
         cmd.bindmount_ro(&js.node, &js.node);
@@ -43,6 +48,6 @@ impl Language for JavaScript {
             cmd.bindmount_ro(mnt, mnt);
         }
 
-        sandbox_run(cmd, &config, workspace, hard_limit)
+        sandbox_run(sandbox, &cmd, &config, &workspace, hard_limit)
     }
 }
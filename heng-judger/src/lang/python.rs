@@ -19,13 +19,15 @@ impl Language for Python {
         unimplemented!()
     }
 
-    fn compile(&self, _: PathBuf, _: &Limit) -> Result<SandboxOutput> {
+    fn compile(&self, _: &dyn Sandbox, _: PathBuf, _: &Limit) -> Result<SandboxOutput> {
         unimplemented!()
     }
 
-    fn run(
+    fn run_with_args(
         &self,
+        sandbox: &dyn Sandbox,
         workspace: PathBuf,
+        args: &[&str],
         stdin: PathBuf,
         stdout: PathBuf,
         stderr: PathBuf,
@@ -34,8 +36,11 @@ impl Language for Python {
         let config = inject::<Config>();
         let python = &config.executor.python;
 
-        let mut cmd = carapace::Command::new(&python.python);
+        let mut cmd = Command::new(&python.python);
         cmd.arg(self.src_name());
+        for arg in args {
+            cmd.arg(arg);
+        }
         cmd.stdio(stdin, stdout, stderr);
 
         cmd.bindmount_ro(&python.python, &python.python);
@@ -43,6 +48,6 @@ impl Language for Python {
             cmd.bindmount_ro(mnt, mnt);
         }
 
-        sandbox_run(cmd, &config, workspace, hard_limit)
+        sandbox_run(sandbox, &cmd, &config, &workspace, hard_limit)
     }
 }
@@ -27,11 +27,16 @@ impl Language for Rust {
         "msg"
     }
 
-    fn compile(&self, workspace: PathBuf, hard_limit: &Limit) -> Result<SandboxOutput> {
+    fn compile(
+        &self,
+        sandbox: &dyn Sandbox,
+        workspace: PathBuf,
+        hard_limit: &Limit,
+    ) -> Result<SandboxOutput> {
         let config = inject::<Config>();
         let rust = &config.executor.rust;
 
-        let mut cmd = carapace::Command::new(&rust.rustc);
+        let mut cmd = Command::new(&rust.rustc);
         cmd.arg_if(self.o2, "-O");
         cmd.arg("-o").arg(self.exe_name());
         cmd.arg(self.src_name());
@@ -42,20 +47,25 @@ impl Language for Rust {
             cmd.bindmount_ro(mnt, mnt);
         }
 
-        sandbox_run(cmd, &config, workspace, hard_limit)
+        sandbox_run(sandbox, &cmd, &config, &workspace, hard_limit)
     }
 
-    fn run(
+    fn run_with_args(
         &self,
+        sandbox: &dyn Sandbox,
         workspace: PathBuf,
+        args: &[&str],
         stdin: PathBuf,
         stdout: PathBuf,
         stderr: PathBuf,
         hard_limit: &Limit,
     ) -> Result<SandboxOutput> {
         let config = inject::<Config>();
-        let mut cmd = carapace::Command::new(self.exe_name());
+        let mut cmd = Command::new(self.exe_name());
+        for arg in args {
+            cmd.arg(arg);
+        }
         cmd.stdio(stdin, stdout, stderr);
-        sandbox_run(cmd, &config, workspace, hard_limit)
+        sandbox_run(sandbox, &cmd, &config, &workspace, hard_limit)
     }
 }
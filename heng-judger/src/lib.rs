@@ -8,35 +8,72 @@ macro_rules! reject_error {
     };
 }
 
+mod archive;
+mod cache;
 mod config;
 mod data;
+mod discover;
 mod exec;
 mod judger;
 pub mod lang;
+pub mod local;
 mod login;
+mod polygon;
+mod profile;
+pub mod sandbox;
+mod score;
+pub mod selftest;
+mod status;
+mod verdict;
 
 pub use self::config::Config;
 use self::data::DataModule;
 use self::exec::ExecutorModule;
 use self::judger::Judger;
+use self::profile::Profile;
+use self::sandbox::Carapace;
 
 use heng_utils::container::{inject, Container};
+use heng_utils::signal;
+
+use heng_protocol::internal::Feature;
 
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::Result;
+use rand::Rng;
+use tokio::{task, time};
 use tokio_tungstenite::MaybeTlsStream;
+use tracing::{error, info, warn};
 
 type WsStream = tokio_tungstenite::WebSocketStream<MaybeTlsStream<tokio::net::TcpStream>>;
 type WsMessage = tokio_tungstenite::tungstenite::Message;
 
+/// initializes modules to serve the controller
 pub fn init(config: Config) -> Result<()> {
-    let data_module = Arc::new(DataModule::new(&config)?);
-    let executor_module = Arc::new(ExecutorModule::new(&config, data_module.clone())?);
+    let data_module = DataModule::new(&config)?;
+    init_with(config, data_module)
+}
+
+/// initializes modules for one-off runs, such as local judges and the self-test
+///
+/// The data directory may be in use by a judger, so it is never cleaned up.
+pub fn init_local(config: Config) -> Result<()> {
+    let data_module = DataModule::shared(&config)?;
+    init_with(config, data_module)
+}
+
+fn init_with(config: Config, data_module: DataModule) -> Result<()> {
+    let data_module = Arc::new(data_module);
+    let sandbox = Arc::new(Carapace::new(&config));
+    let executor_module = Arc::new(ExecutorModule::new(&config, data_module.clone(), sandbox)?);
+    let profile = Arc::new(Profile::detect(&config)?);
 
     let mut container = Container::new();
 
     container.register(Arc::new(config));
+    container.register(profile);
     container.register(data_module);
     container.register(executor_module);
 
@@ -44,14 +81,80 @@ pub fn init(config: Config) -> Result<()> {
     Ok(())
 }
 
+/// serves the controller until a shutdown signal is received
 pub async fn run() -> Result<()> {
     let config = inject::<Config>();
+    if config.executor.self_test {
+        selftest::apply().await?;
+    }
+    let judger = Judger::new(&config, inject::<Profile>().max_concurrency);
+
+    // the connection is kept while draining, so that results can be reported
+    let supervisor = task::spawn(supervise(config.clone(), judger.clone()));
+
+    signal::shutdown().await;
+    info!("shutting down");
+
+    let shutdown_timeout = Duration::from_millis(config.judger.shutdown_timeout);
+    judger.shutdown(shutdown_timeout).await;
+    supervisor.abort();
+
+    inject::<ExecutorModule>().clean_workspaces();
+    info!("judger is stopped");
+    Ok(())
+}
+
+/// connects to the controller and reconnects with backoff when disconnected
+async fn supervise(config: Arc<Config>, judger: Arc<Judger>) {
+    let mut failures = 0;
+    loop {
+        match connect(&config).await {
+            Ok((ws_stream, features)) => {
+                failures = 0;
+                match judger.serve(ws_stream, features).await {
+                    Ok(()) => warn!("disconnected from controller"),
+                    Err(err) => error!(%err, "disconnected from controller"),
+                }
+            }
+            Err(err) => {
+                error!(%err, "failed to connect to controller");
+                failures += 1;
+            }
+        }
+
+        let delay = reconnect_delay(&config.judger.reconnect, failures);
+        info!(?delay, failures, "reconnecting");
+        time::sleep(delay).await;
+    }
+}
+
+async fn connect(config: &Config) -> Result<(WsStream, Vec<Feature>)> {
     let remote_domain = &*config.judger.remote_domain;
     let access_key = &config.judger.access_key;
     let secret_key = &config.judger.secret_key;
 
-    let token = login::get_token(remote_domain, access_key, secret_key).await?;
-    let ws_stream = login::connect_ws(remote_domain, access_key, secret_key, &*token).await?;
+    let profile = inject::<Profile>();
+    let languages = inject::<ExecutorModule>().languages();
+    let output = login::get_token(
+        remote_domain,
+        access_key,
+        secret_key,
+        &profile,
+        judger::FEATURES,
+        &languages,
+    )
+    .await?;
+    let ws_stream =
+        login::connect_ws(remote_domain, access_key, secret_key, &*output.token).await?;
+    Ok((ws_stream, output.features))
+}
 
-    Judger::run(ws_stream).await
+/// exponential backoff with equal jitter
+fn reconnect_delay(reconnect: &config::Reconnect, failures: u32) -> Duration {
+    let backoff = reconnect
+        .initial_backoff
+        .saturating_mul(1 << failures.min(16))
+        .min(reconnect.max_backoff);
+    let delay = rand::thread_rng().gen_range(backoff / 2..=backoff);
+    Duration::from_millis(delay)
 }
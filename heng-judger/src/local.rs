@@ -0,0 +1,265 @@
+use crate::exec::{ExecutorModule, Progress};
+use crate::polygon;
+
+use heng_protocol::common::{
+    CompilerLimit, Environment, Executable, File, Judge, JudgeResult, Limit, RuntimeLimit, Test,
+    TestPolicy,
+};
+use heng_protocol::internal::ws_json::CreateJudgeArgs;
+use heng_protocol::internal::JudgeState;
+use heng_utils::container::inject;
+
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde_json::Map;
+use tokio::sync::watch;
+use ubyte::ByteUnit;
+use uuid::Uuid;
+
+/// reads a task from a JSON file, or a TOML file by its extension
+///
+/// The task id is replaced by a random one, so that concurrent runs of a file
+/// never share a workspace.
+pub fn load_task(path: &Path) -> Result<CreateJudgeArgs> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("failed to read task: path = {}", path.display()))?;
+    let mut args: CreateJudgeArgs = match path.extension().and_then(|e| e.to_str()) {
+        Some("toml") => toml::from_str(&content)?,
+        _ => serde_json::from_str(&content)?,
+    };
+    args.id = Uuid::new_v4().to_string();
+    Ok(args)
+}
+
+/// a source file to be judged with a problem directory
+pub struct ProblemTask<'a> {
+    pub problem_dir: &'a Path,
+    pub source: &'a Path,
+    pub language: &'a str,
+    /// the package limit or 1 second by default
+    pub time_limit: Option<u64>,
+    /// the package limit or 256 MiB by default
+    pub memory_limit: Option<u64>,
+}
+
+impl ProblemTask<'_> {
+    /// builds a task whose cases are discovered in the problem directory,
+    /// or imported from a polygon package
+    pub fn into_args(self) -> Result<CreateJudgeArgs> {
+        let source = fs::read_to_string(self.source)
+            .with_context(|| format!("failed to read source: path = {}", self.source.display()))?;
+
+        // zero limits are filled by the package
+        let (default_time, default_memory) = if polygon::is_package(self.problem_dir) {
+            (0, 0)
+        } else {
+            (1000, 256 * 1024 * 1024)
+        };
+
+        let user = Executable {
+            source: File::Direct {
+                content: source,
+                hashsum: None,
+                base64: false,
+            },
+            environment: Environment {
+                language: self.language.to_owned(),
+                system: std::env::consts::OS.to_owned(),
+                arch: std::env::consts::ARCH.to_owned(),
+                options: Map::new(),
+            },
+            // the hard limits apply to compilers and output
+            limit: Limit {
+                runtime: RuntimeLimit {
+                    memory: self.memory_limit.unwrap_or(default_memory),
+                    cpu_time: self.time_limit.unwrap_or(default_time),
+                    output: u64::MAX,
+                },
+                compiler: CompilerLimit {
+                    memory: u64::MAX,
+                    cpu_time: u64::MAX,
+                    output: u64::MAX,
+                    message: u64::MAX,
+                },
+            },
+        };
+
+        Ok(CreateJudgeArgs {
+            id: Uuid::new_v4().to_string(),
+            data: Some(pack_dir(self.problem_dir)?),
+            dynamic_files: None,
+            judge: Judge::Normal { user },
+            test: Test {
+                cases: Vec::new(),
+                policy: TestPolicy::All,
+                subtasks: None,
+                discover: true,
+            },
+            diagnostics: true,
+        })
+    }
+}
+
+/// archives a directory as one-off data, which is extracted by the data module
+fn pack_dir(dir: &Path) -> Result<File> {
+    if !dir.is_dir() {
+        anyhow::bail!("not a directory: path = {}", dir.display());
+    }
+    let mut builder = tar::Builder::new(Vec::new());
+    builder.append_dir_all("", dir)?;
+    let content = builder.into_inner()?;
+    Ok(File::Binary {
+        content,
+        hashsum: None,
+    })
+}
+
+/// runs the executor pipeline without a controller
+pub async fn judge(args: CreateJudgeArgs) -> Result<JudgeResult> {
+    let (progress_tx, _progress_rx) = watch::channel(Progress {
+        state: JudgeState::Pending,
+        current_case: None,
+        cases: Vec::new(),
+    });
+    inject::<ExecutorModule>().exec(args, &progress_tx).await
+}
+
+/// formats a result as a plain text table
+pub fn format_table(result: &JudgeResult) -> String {
+    let mut table = String::new();
+    let _ = writeln!(
+        table,
+        "{:>4}  {:<28} {:>8} {:>10} {:>6}",
+        "case", "result", "time", "memory", "score"
+    );
+    for (i, case) in result.cases.iter().enumerate() {
+        let score = match case.score {
+            Some(score) => format!("{:.2}", score),
+            None => "-".to_owned(),
+        };
+        let _ = writeln!(
+            table,
+            "{:>4}  {:<28} {:>6}ms {:>10} {:>6}",
+            i + 1,
+            format!("{:?}", case.kind),
+            case.time,
+            ByteUnit::from(case.memory).to_string(),
+            score
+        );
+        if let Some(ref message) = case.message {
+            let _ = writeln!(table, "      {}", message.trim_end());
+        }
+    }
+
+    for (i, subtask) in result.subtasks.iter().flatten().enumerate() {
+        let _ = writeln!(
+            table,
+            "subtask {}: {:?} {:.2}",
+            i + 1,
+            subtask.kind,
+            subtask.score
+        );
+    }
+    if let Some(score) = result.score {
+        let _ = writeln!(table, "score: {:.2}", score);
+    }
+
+    let compile_message = result
+        .extra
+        .as_ref()
+        .and_then(|extra| extra.user.as_ref())
+        .and_then(|user| user.compile_message.as_ref());
+    if let Some(message) = compile_message {
+        let _ = writeln!(table, "compile message:\n{}", message.trim_end());
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::archive::{self, ExtractLimit};
+
+    use heng_protocol::common::{JudgeCaseResult, JudgeResultKind};
+    use heng_utils::temp_dir::TempDir;
+
+    #[test]
+    fn problem_task() {
+        let dir = TempDir::new("heng-local").unwrap();
+        fs::create_dir_all(dir.join("data")).unwrap();
+        fs::write(dir.join("data/1.in"), "1 2").unwrap();
+        fs::write(dir.join("data/1.out"), "3").unwrap();
+        fs::write(dir.join("main.py"), "print(sum(map(int, input().split())))").unwrap();
+
+        let args = ProblemTask {
+            problem_dir: &dir.join("data"),
+            source: &dir.join("main.py"),
+            language: "python",
+            time_limit: None,
+            memory_limit: Some(64 << 20),
+        }
+        .into_args()
+        .unwrap();
+
+        assert!(args.test.cases.is_empty() && args.test.discover);
+        let user = match args.judge {
+            Judge::Normal { ref user } => user,
+            _ => panic!("expected normal judge"),
+        };
+        assert_eq!(user.limit.runtime.cpu_time, 1000);
+        assert_eq!(user.limit.runtime.memory, 64 << 20);
+
+        let content = match args.data {
+            Some(File::Binary { content, .. }) => content,
+            _ => panic!("expected binary data"),
+        };
+        let mut archive = tar::Archive::new(&*content);
+        let mut names: Vec<String> = archive
+            .entries()
+            .unwrap()
+            .map(|e| e.unwrap().path().unwrap().display().to_string())
+            .filter(|name| !name.is_empty())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["1.in", "1.out"]);
+
+        // the archive is accepted by the data module
+        let archive_path = dir.join("data.archive");
+        fs::write(&archive_path, &content).unwrap();
+        let limit = ExtractLimit {
+            max_uncompressed_size: u64::MAX,
+            max_entries: 16,
+            max_ratio: 100,
+        };
+        archive::extract(&archive_path, &dir.join("extracted"), &limit).unwrap();
+        assert_eq!(fs::read(dir.join("extracted/1.in")).unwrap(), b"1 2");
+    }
+
+    #[test]
+    fn table() {
+        let case = JudgeCaseResult {
+            kind: JudgeResultKind::WrongAnswer,
+            time: 12,
+            memory: 1024,
+            score: Some(0.0),
+            message: Some("expected 3\n".into()),
+            diagnostics: None,
+        };
+        let result = JudgeResult {
+            cases: vec![case],
+            extra: None,
+            subtasks: None,
+            score: Some(0.0),
+            discovered_cases: None,
+        };
+        let table = format_table(&result);
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[1].contains("WrongAnswer") && lines[1].contains("12ms"));
+        assert_eq!(lines[2].trim(), "expected 3");
+        assert_eq!(lines[3], "score: 0.00");
+    }
+}
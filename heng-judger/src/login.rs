@@ -1,6 +1,8 @@
+use crate::profile::Profile;
 use crate::WsStream;
 
 use heng_protocol::internal::http::{AcquireTokenOutput, AcquireTokenRequest};
+use heng_protocol::internal::{is_compatible_version, Feature, PROTOCOL_VERSION};
 use heng_protocol::signature::calc_signature;
 
 use anyhow::{format_err, Result};
@@ -8,15 +10,16 @@ use reqwest::header::HeaderValue;
 use tracing::{error, info};
 
 #[tracing::instrument(err)]
-pub async fn get_token(remote_domain: &str, access_key: &str, secret_key: &str) -> Result<String> {
+pub async fn get_token(
+    remote_domain: &str,
+    access_key: &str,
+    secret_key: &str,
+    profile: &Profile,
+    features: &[Feature],
+    languages: &[String],
+) -> Result<AcquireTokenOutput> {
     let token_url = format!("http://{}/v1/judgers/token", remote_domain);
-
-    let body = AcquireTokenRequest {
-        max_task_count: 8,
-        name: None,
-        core_count: None,
-        software: None,
-    };
+    let body = token_request(profile, features, languages);
 
     let http_client = reqwest::Client::new();
     let mut req = http_client.post(&token_url).json(&body).build()?;
@@ -45,7 +48,16 @@ pub async fn get_token(remote_domain: &str, access_key: &str, secret_key: &str)
 
     if res.status().is_success() {
         let output = res.json::<AcquireTokenOutput>().await?;
-        Ok(output.token)
+        if !is_compatible_version(output.protocol_version) {
+            error!(
+                remote_version = output.protocol_version,
+                local_version = PROTOCOL_VERSION,
+                "incompatible protocol version"
+            );
+            return Err(format_err!("incompatible protocol version"));
+        }
+        info!(?output.features, "negotiated features");
+        Ok(output)
     } else {
         let status = res.status();
         let text = res.text().await.unwrap();
@@ -54,6 +66,24 @@ pub async fn get_token(remote_domain: &str, access_key: &str, secret_key: &str)
     }
 }
 
+/// the capacity, features and languages which are advertised to the controller
+pub fn token_request(
+    profile: &Profile,
+    features: &[Feature],
+    languages: &[String],
+) -> AcquireTokenRequest {
+    AcquireTokenRequest {
+        max_task_count: profile.max_concurrency,
+        instance_id: profile.instance_id.clone(),
+        name: profile.name.clone(),
+        core_count: Some(profile.core_count),
+        software: Some(profile.software.clone()),
+        protocol_version: PROTOCOL_VERSION,
+        features: features.to_owned(),
+        languages: Some(languages.to_owned()),
+    }
+}
+
 #[tracing::instrument(err)]
 pub async fn connect_ws(
     remote_domain: &str,
@@ -1,18 +1,70 @@
+use heng_judger::local::{self, ProblemTask};
+use heng_judger::selftest;
 use heng_judger::Config;
 use heng_utils::tracing::setup_tracing;
 
-use std::env;
+use std::path::{Path, PathBuf};
 
 use anyhow::Result;
 use dotenv::dotenv;
+use structopt::StructOpt;
 use tracing::info;
+use ubyte::ByteUnit;
 
 const CONFIG_PATH: &str = "heng-judger.toml";
 
-#[tracing::instrument(err)]
-fn load_config() -> Result<Config> {
-    let path = env::current_dir()?.join(CONFIG_PATH);
+#[derive(Debug, StructOpt)]
+#[structopt(name = "heng-judger")]
+struct Opt {
+    /// the config file
+    #[structopt(long, default_value = CONFIG_PATH)]
+    config: PathBuf,
+
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, StructOpt)]
+enum Command {
+    /// judges locally without a controller
+    Judge(JudgeOpt),
+    /// compiles and runs a hello-world program of every language
+    SelfTest,
+}
+
+#[derive(Debug, StructOpt)]
+struct JudgeOpt {
+    /// a `CreateJudgeArgs` in JSON, or in TOML with the `.toml` extension
+    #[structopt(required_unless = "problem", conflicts_with = "problem")]
+    task: Option<PathBuf>,
+
+    /// a problem directory with test data, or a polygon package
+    #[structopt(long, requires_all = &["source", "language"])]
+    problem: Option<PathBuf>,
+
+    /// the source file to judge with `--problem`
+    #[structopt(long)]
+    source: Option<PathBuf>,
+
+    /// the language of `--source`, such as "cpp" or "python"
+    #[structopt(long)]
+    language: Option<String>,
+
+    /// the time limit in milliseconds
+    #[structopt(long)]
+    time: Option<u64>,
+
+    /// the memory limit, such as "256MiB"
+    #[structopt(long)]
+    memory: Option<ByteUnit>,
 
+    /// prints the result as JSON
+    #[structopt(long)]
+    json: bool,
+}
+
+#[tracing::instrument(err)]
+fn load_config(path: &Path) -> Result<Config> {
     info!("loading config from {}", path.display());
     let config = Config::from_file(&path)?;
     info!("config is loaded:\n{:#?}", config);
@@ -25,7 +77,50 @@ async fn main() -> Result<()> {
     dotenv().ok();
     setup_tracing();
 
-    let config = load_config()?;
-    heng_judger::init(config)?;
-    heng_judger::run().await
+    let opt = Opt::from_args();
+    let config = load_config(&opt.config)?;
+    match opt.command {
+        None => heng_judger::init(config)?,
+        Some(_) => heng_judger::init_local(config)?,
+    }
+
+    match opt.command {
+        None => heng_judger::run().await,
+        Some(Command::Judge(opt)) => judge(opt).await,
+        Some(Command::SelfTest) => self_test().await,
+    }
+}
+
+async fn self_test() -> Result<()> {
+    let reports = selftest::apply().await?;
+    print!("{}", selftest::format_table(&reports));
+
+    let failed = reports.iter().filter(|r| !r.passed).count();
+    if failed > 0 {
+        anyhow::bail!("{} of {} languages failed", failed, reports.len());
+    }
+    Ok(())
+}
+
+async fn judge(opt: JudgeOpt) -> Result<()> {
+    let args = match (opt.task, opt.problem) {
+        (Some(task), _) => local::load_task(&task)?,
+        (None, Some(problem_dir)) => ProblemTask {
+            problem_dir: &problem_dir,
+            source: opt.source.as_deref().unwrap(),
+            language: opt.language.as_deref().unwrap(),
+            time_limit: opt.time,
+            memory_limit: opt.memory.map(|m| m.as_u64()),
+        }
+        .into_args()?,
+        (None, None) => unreachable!(),
+    };
+
+    let result = local::judge(args).await?;
+    if opt.json {
+        println!("{}", serde_json::to_string_pretty(&result)?);
+    } else {
+        print!("{}", local::format_table(&result));
+    }
+    Ok(())
 }
@@ -0,0 +1,496 @@
+use heng_protocol::common::{
+    Environment, Executable, File, Judge, Limit, RuntimeLimit, Subtask, SubtaskScoring, Test,
+    TestCase, TestPolicy,
+};
+use heng_protocol::error::ErrorCode;
+use heng_protocol::internal::ErrorInfo;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{format_err, Context, Result};
+use roxmltree::{Document, Node};
+use serde_json::{Map, Value};
+
+const PROBLEM_XML: &str = "problem.xml";
+
+/// the judge configuration of a Polygon package
+#[derive(Debug)]
+pub struct Package {
+    pub cases: Vec<TestCase>,
+    pub subtasks: Option<Vec<Subtask>>,
+    pub time_limit: u64,   // milliseconds
+    pub memory_limit: u64, // bytes
+    pub checker: Option<Asset>,
+    pub interactor: Option<Asset>,
+    /// paths of resource files, such as `files/testlib.h`
+    pub resources: Vec<String>,
+}
+
+/// a source file in the package
+#[derive(Debug)]
+pub struct Asset {
+    pub path: String,
+    pub environment: Environment,
+}
+
+pub fn is_package(data_dir: &Path) -> bool {
+    data_dir.join(PROBLEM_XML).is_file()
+}
+
+pub fn load_package(data_dir: &Path) -> Result<Package> {
+    let xml = fs::read_to_string(data_dir.join(PROBLEM_XML))?;
+    parse_problem_xml(&xml).context("invalid problem.xml")
+}
+
+impl Package {
+    /// fills the fields which are left empty by the request
+    ///
+    /// + `test.cases` and `test.subtasks` are taken from the package
+    /// + zero limits of the user executable are taken from the package
+    /// + a normal judge becomes an interactive judge or a special judge,
+    ///   and the checker of an interactive package checks the interactor output
+    pub fn apply(&self, data_dir: &Path, judge: &mut Judge, test: &mut Test) -> Result<()> {
+        if test.cases.is_empty() && !test.discover {
+            test.cases = self.cases.clone();
+            if test.subtasks.is_none() {
+                test.subtasks = self.subtasks.clone();
+            }
+        }
+
+        if let Judge::Normal { user } = judge {
+            let runtime = &mut user.limit.runtime;
+            fill_zero(&mut runtime.cpu_time, self.time_limit);
+            fill_zero(&mut runtime.memory, self.memory_limit);
+            fill_zero(&mut runtime.output, u64::MAX);
+
+            let compiler = &mut user.limit.compiler;
+            fill_zero(&mut compiler.cpu_time, u64::MAX);
+            fill_zero(&mut compiler.memory, u64::MAX);
+            fill_zero(&mut compiler.output, u64::MAX);
+            fill_zero(&mut compiler.message, u64::MAX);
+
+            // assets are trusted, so they are only limited by hard limits
+            let asset_limit = Limit {
+                runtime: RuntimeLimit {
+                    memory: u64::MAX,
+                    cpu_time: u64::MAX,
+                    output: u64::MAX,
+                },
+                compiler: user.limit.compiler.clone(),
+            };
+
+            let to_executable = |asset: &Asset| -> Result<Executable> {
+                let content = fs::read(data_dir.join(&asset.path))
+                    .with_context(|| format!("failed to read asset: path = {}", asset.path))?;
+                Ok(Executable {
+                    source: File::Binary {
+                        content,
+                        hashsum: None,
+                    },
+                    environment: asset.environment.clone(),
+                    limit: asset_limit.clone(),
+                })
+            };
+
+            let user = user.clone();
+            if let Some(ref interactor) = self.interactor {
+                let interactor = to_executable(interactor)?;
+                let checker = self.checker.as_ref().map(to_executable).transpose()?;
+                *judge = Judge::Interactive {
+                    user,
+                    interactor,
+                    checker,
+                };
+            } else if let Some(ref checker) = self.checker {
+                let spj = to_executable(checker)?;
+                *judge = Judge::Special { user, spj };
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn fill_zero(x: &mut u64, value: u64) {
+    if *x == 0 {
+        *x = value;
+    }
+}
+
+fn parse_problem_xml(xml: &str) -> Result<Package> {
+    let doc = Document::parse(xml)?;
+    let problem = doc.root_element();
+
+    let judging = child(problem, "judging")?;
+    let testset = judging
+        .children()
+        .filter(|n| n.has_tag_name("testset"))
+        .find(|n| n.attribute("name") == Some("tests"))
+        .or_else(|| judging.children().find(|n| n.has_tag_name("testset")))
+        .ok_or_else(|| format_err!("no testset"))?;
+
+    let time_limit = child_number(testset, "time-limit")?;
+    let memory_limit = child_number(testset, "memory-limit")?;
+    let test_count = child_number(testset, "test-count")?;
+    let input_pattern = child_text(testset, "input-path-pattern")?;
+    let answer_pattern = child_text(testset, "answer-path-pattern")?;
+
+    let cases = (1..=test_count)
+        .map(|i| {
+            Ok(TestCase {
+                input: format_pattern(input_pattern, i)?,
+                output: format_pattern(answer_pattern, i)?,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let tests: Vec<Node> = match testset.children().find(|n| n.has_tag_name("tests")) {
+        Some(tests) => tests
+            .children()
+            .filter(|n| n.has_tag_name("test"))
+            .collect(),
+        None => Vec::new(),
+    };
+    let subtasks = parse_subtasks(testset, &tests)?;
+
+    let assets = problem.children().find(|n| n.has_tag_name("assets"));
+    let asset = |name: &str| -> Result<Option<Asset>> {
+        let node = assets.and_then(|a| a.children().find(|n| n.has_tag_name(name)));
+        match node {
+            None => Ok(None),
+            Some(node) => {
+                let source = child(node, "source")?;
+                let path = source
+                    .attribute("path")
+                    .ok_or_else(|| format_err!("no source path of {}", name))?;
+                let source_type = source.attribute("type").unwrap_or("cpp.g++17");
+                Ok(Some(Asset {
+                    path: path.to_owned(),
+                    environment: to_environment(source_type)?,
+                }))
+            }
+        }
+    };
+    let checker = asset("checker")?;
+    let interactor = asset("interactor")?;
+
+    let resources = problem
+        .children()
+        .filter(|n| n.has_tag_name("files"))
+        .flat_map(|n| n.children())
+        .filter(|n| n.has_tag_name("resources"))
+        .flat_map(|n| n.children())
+        .filter(|n| n.has_tag_name("file"))
+        .filter_map(|n| n.attribute("path"))
+        .map(|path| path.to_owned())
+        .collect();
+
+    Ok(Package {
+        cases,
+        subtasks,
+        time_limit,
+        memory_limit,
+        checker,
+        interactor,
+        resources,
+    })
+}
+
+/// converts groups into subtasks
+///
+/// A group with `points-policy="each-test"` becomes a single-case subtask per test,
+/// so that tests keep their own points.
+/// Tests without groups become single-case subtasks if they have points.
+fn parse_subtasks(testset: Node, tests: &[Node]) -> Result<Option<Vec<Subtask>>> {
+    let has_points = tests.iter().any(|t| t.attribute("points").is_some());
+    if !has_points {
+        return Ok(None);
+    }
+
+    let points = |test: &Node| -> Result<f64> {
+        match test.attribute("points") {
+            Some(p) => Ok(p.parse()?),
+            None => Ok(0.0),
+        }
+    };
+
+    let single_case = |i: usize, test: &Node, dependencies: Vec<u32>| -> Result<Subtask> {
+        Ok(Subtask {
+            cases: vec![i as u32],
+            score: points(test)?,
+            policy: TestPolicy::Fuse,
+            scoring: SubtaskScoring::Min,
+            dependencies,
+        })
+    };
+
+    let groups: Vec<Node> = match testset.children().find(|n| n.has_tag_name("groups")) {
+        Some(groups) => groups
+            .children()
+            .filter(|n| n.has_tag_name("group"))
+            .collect(),
+        None => Vec::new(),
+    };
+
+    let mut subtasks = Vec::new();
+    // the subtasks of each group
+    let mut group_indices: HashMap<&str, Vec<u32>> = HashMap::new();
+
+    for group in &groups {
+        let name = group
+            .attribute("name")
+            .ok_or_else(|| format_err!("no group name"))?;
+
+        let members: Vec<(usize, &Node)> = tests
+            .iter()
+            .enumerate()
+            .filter(|(_, test)| test.attribute("group") == Some(name))
+            .collect();
+        if members.is_empty() {
+            continue;
+        }
+
+        let mut dependencies = Vec::new();
+        let deps = group
+            .children()
+            .filter(|n| n.has_tag_name("dependencies"))
+            .flat_map(|n| n.children())
+            .filter_map(|n| n.attribute("group"));
+        for dep in deps {
+            match group_indices.get(dep) {
+                Some(indices) => dependencies.extend(indices),
+                None => return Err(format_err!("invalid group dependency: {}", dep)),
+            }
+        }
+
+        let start = subtasks.len() as u32;
+        if group.attribute("points-policy") == Some("each-test") {
+            for &(i, test) in &members {
+                subtasks.push(single_case(i, test, dependencies.clone())?);
+            }
+        } else {
+            let mut score = 0.0;
+            for &(_, test) in &members {
+                score += points(test)?;
+            }
+            subtasks.push(Subtask {
+                cases: members.iter().map(|&(i, _)| i as u32).collect(),
+                score,
+                policy: TestPolicy::Fuse,
+                scoring: SubtaskScoring::Min,
+                dependencies,
+            });
+        }
+        group_indices.insert(name, (start..subtasks.len() as u32).collect());
+    }
+
+    for (i, test) in tests.iter().enumerate() {
+        let has_group = test
+            .attribute("group")
+            .map_or(false, |g| group_indices.contains_key(g));
+        if has_group {
+            continue;
+        }
+        subtasks.push(single_case(i, test, Vec::new())?);
+    }
+
+    Ok(Some(subtasks))
+}
+
+/// converts a Polygon source type into an environment
+fn to_environment(source_type: &str) -> Result<Environment> {
+    let mut options = Map::new();
+    let language = if source_type.starts_with("cpp.") {
+        // such as `cpp.g++11`, `cpp.g++17` and `cpp.msys2-mingw64-9-g++17`
+        let std = match source_type.rsplit("++").next() {
+            Some("11") => "cpp11",
+            Some("14") => "cpp14",
+            _ => "cpp17",
+        };
+        options.insert("std".into(), Value::String(std.into()));
+        "cpp"
+    } else if source_type.starts_with("c.") {
+        "c"
+    } else if source_type.starts_with("java") {
+        "java"
+    } else if source_type.starts_with("python.3") || source_type.starts_with("python3") {
+        "python"
+    } else if source_type.starts_with("rust") {
+        "rust"
+    } else {
+        reject_error!(
+            ErrorCode::NotSupported,
+            Some(format!("unsupported source type: {}", source_type))
+        )
+    };
+    Ok(Environment {
+        language: language.into(),
+        system: String::new(),
+        arch: String::new(),
+        options,
+    })
+}
+
+/// formats a path pattern such as `tests/%02d`
+fn format_pattern(pattern: &str, index: u64) -> Result<String> {
+    let pos = pattern
+        .find('%')
+        .ok_or_else(|| format_err!("invalid path pattern: {}", pattern))?;
+    let (prefix, spec) = pattern.split_at(pos);
+    let end = spec
+        .find('d')
+        .ok_or_else(|| format_err!("invalid path pattern: {}", pattern))?;
+    let flags = &spec[1..end];
+    let suffix = &spec[end + 1..];
+
+    let zero_pad = flags.starts_with('0');
+    let width: usize = if flags.is_empty() { 0 } else { flags.parse()? };
+
+    let number = if zero_pad {
+        format!("{:0width$}", index, width = width)
+    } else {
+        format!("{:width$}", index, width = width)
+    };
+    Ok(format!("{}{}{}", prefix, number, suffix))
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Result<Node<'a, 'input>> {
+    node.children()
+        .find(|n| n.has_tag_name(name))
+        .ok_or_else(|| format_err!("no element: {}", name))
+}
+
+fn child_text<'a>(node: Node<'a, '_>, name: &str) -> Result<&'a str> {
+    child(node, name)?
+        .text()
+        .map(str::trim)
+        .ok_or_else(|| format_err!("empty element: {}", name))
+}
+
+fn child_number(node: Node, name: &str) -> Result<u64> {
+    Ok(child_text(node, name)?.parse()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::score;
+
+    use heng_protocol::common::{JudgeCaseResult, JudgeResultKind};
+
+    const PROBLEM_XML: &str = r#"<?xml version="1.0" encoding="utf-8" standalone="no"?>
+<problem revision="3" short-name="a-plus-b">
+    <judging input-file="" output-file="">
+        <testset name="tests">
+            <time-limit>2000</time-limit>
+            <memory-limit>268435456</memory-limit>
+            <test-count>4</test-count>
+            <input-path-pattern>tests/%02d</input-path-pattern>
+            <answer-path-pattern>tests/%02d.a</answer-path-pattern>
+            <tests>
+                <test method="manual" points="0" group="0" sample="true"/>
+                <test method="manual" points="20" group="1"/>
+                <test method="generated" points="20" group="1"/>
+                <test method="generated" points="60" group="2"/>
+            </tests>
+            <groups>
+                <group name="0" points-policy="complete-group"/>
+                <group name="1" points-policy="each-test">
+                    <dependencies><dependency group="0"/></dependencies>
+                </group>
+                <group name="2" points-policy="complete-group">
+                    <dependencies><dependency group="0"/><dependency group="1"/></dependencies>
+                </group>
+            </groups>
+        </testset>
+    </judging>
+    <files>
+        <resources>
+            <file path="files/testlib.h" type="h.g++"/>
+        </resources>
+    </files>
+    <assets>
+        <checker name="std::ncmp.cpp" type="testlib">
+            <source path="files/check.cpp" type="cpp.g++17"/>
+        </checker>
+    </assets>
+</problem>
+"#;
+
+    #[test]
+    fn parse() {
+        let package = parse_problem_xml(PROBLEM_XML).unwrap();
+        assert_eq!(package.time_limit, 2000);
+        assert_eq!(package.memory_limit, 256 * 1024 * 1024);
+        assert_eq!(package.cases.len(), 4);
+        assert_eq!(package.cases[3].input, "tests/04");
+        assert_eq!(package.cases[3].output, "tests/04.a");
+        assert_eq!(package.resources, ["files/testlib.h"]);
+
+        let checker = package.checker.unwrap();
+        assert_eq!(checker.path, "files/check.cpp");
+        assert_eq!(checker.environment.language, "cpp");
+        assert!(package.interactor.is_none());
+
+        // group 1 is split into a subtask per test
+        let subtasks = package.subtasks.unwrap();
+        assert_eq!(subtasks.len(), 4);
+        assert_eq!(subtasks[1].cases, [1]);
+        assert_eq!(subtasks[1].score, 20.0);
+        assert_eq!(subtasks[1].dependencies, [0]);
+        assert_eq!(subtasks[2].cases, [2]);
+        assert_eq!(subtasks[2].dependencies, [0]);
+        assert_eq!(subtasks[3].cases, [3]);
+        assert_eq!(subtasks[3].dependencies, [0, 1, 2]);
+    }
+
+    #[test]
+    fn each_test_points() {
+        let xml = r#"<problem>
+    <judging>
+        <testset name="tests">
+            <time-limit>1000</time-limit>
+            <memory-limit>268435456</memory-limit>
+            <test-count>2</test-count>
+            <input-path-pattern>tests/%02d</input-path-pattern>
+            <answer-path-pattern>tests/%02d.a</answer-path-pattern>
+            <tests>
+                <test points="10" group="1"/>
+                <test points="30" group="1"/>
+            </tests>
+            <groups>
+                <group name="1" points-policy="each-test"/>
+            </groups>
+        </testset>
+    </judging>
+</problem>
+"#;
+        let package = parse_problem_xml(xml).unwrap();
+        let subtasks = package.subtasks.unwrap();
+
+        let case = |kind| JudgeCaseResult {
+            kind,
+            time: 0,
+            memory: 0,
+            score: None,
+            message: None,
+            diagnostics: None,
+        };
+        let cases = [
+            case(JudgeResultKind::Accepted),
+            case(JudgeResultKind::WrongAnswer),
+        ];
+        let (results, total) = score::evaluate(&subtasks, &cases);
+        assert_eq!(results.len(), 2);
+        assert_eq!(total, 10.0);
+    }
+
+    #[test]
+    fn pattern() {
+        assert_eq!(format_pattern("tests/%02d", 3).unwrap(), "tests/03");
+        assert_eq!(format_pattern("tests/%d.a", 12).unwrap(), "tests/12.a");
+        assert!(format_pattern("tests/01", 1).is_err());
+    }
+}
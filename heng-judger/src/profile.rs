@@ -0,0 +1,86 @@
+use crate::config::{self, Config, MaxConcurrency};
+use crate::status;
+
+use anyhow::Result;
+use tracing::info;
+use uuid::Uuid;
+
+/// the capacity and environment reported to the controller
+#[derive(Debug, Clone)]
+pub struct Profile {
+    /// tells judgers sharing an access key apart
+    pub instance_id: String,
+    pub name: Option<String>,
+    pub max_concurrency: u32,
+    pub core_count: u32,
+    pub software: String,
+}
+
+impl Profile {
+    pub fn detect(config: &Config) -> Result<Self> {
+        let core_count = num_cpus::get() as u32;
+        let max_concurrency = match config.judger.max_concurrency {
+            MaxConcurrency::Fixed(n) => n,
+            MaxConcurrency::Auto(_) => {
+                let memory_total = status::read_memory_total()?;
+                let memory_limit = config.executor.hard_limit.memory.as_u64();
+                auto_concurrency(core_count, memory_total, memory_limit)
+            }
+        };
+        let profile = Self {
+            instance_id: Uuid::new_v4().to_string(),
+            name: config.judger.name.clone(),
+            max_concurrency,
+            core_count,
+            software: software(&config.executor),
+        };
+        info!(?profile, "judger profile");
+        Ok(profile)
+    }
+}
+
+/// a judge for each core, as long as every judge can reach the memory hard limit
+fn auto_concurrency(core_count: u32, memory_total: u64, memory_limit: u64) -> u32 {
+    let by_memory = memory_total / memory_limit.max(1);
+    u64::from(core_count).min(by_memory).max(1).min(64) as u32
+}
+
+/// names of the configured toolchains
+fn software(executor: &config::Executor) -> String {
+    const MAX_LEN: usize = 256;
+
+    let tools = [
+        &executor.c_cpp.gcc,
+        &executor.c_cpp.gxx,
+        &executor.java.javac,
+        &executor.java.java,
+        &executor.javascript.node,
+        &executor.python.python,
+        &executor.rust.rustc,
+    ];
+
+    let mut software = String::new();
+    for name in tools.iter().filter_map(|path| path.file_name()?.to_str()) {
+        let sep = if software.is_empty() { "" } else { ", " };
+        if software.len() + sep.len() + name.len() > MAX_LEN {
+            break;
+        }
+        software.push_str(sep);
+        software.push_str(name);
+    }
+    software
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn auto() {
+        const GIB: u64 = 1 << 30;
+        assert_eq!(auto_concurrency(8, 16 * GIB, 512 << 20), 8);
+        assert_eq!(auto_concurrency(8, 2 * GIB, 512 << 20), 4);
+        assert_eq!(auto_concurrency(8, GIB / 4, 512 << 20), 1);
+        assert_eq!(auto_concurrency(128, 1024 * GIB, GIB), 64);
+    }
+}
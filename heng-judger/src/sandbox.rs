@@ -0,0 +1,181 @@
+pub mod mock;
+
+use crate::lang::Limit;
+use crate::Config;
+
+use heng_utils::math::roundup_div;
+
+use std::ffi::{OsStr, OsString};
+use std::mem;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use serde::Serialize;
+use tracing::debug;
+
+/// runs commands with limits, whose root directories are their workspaces
+pub trait Sandbox: Send + Sync {
+    fn run(&self, cmd: &Command, workspace: &Path, limit: &Limit) -> Result<SandboxOutput>;
+}
+
+/// a command to run in a sandbox
+///
+/// Relative paths are resolved in the workspace.
+#[derive(Debug, Clone)]
+pub struct Command {
+    pub bin: PathBuf,
+    pub args: Vec<OsString>,
+    pub stdin: Option<PathBuf>,
+    pub stdout: Option<PathBuf>,
+    pub stderr: Option<PathBuf>,
+    /// read-only bind mounts as `(source, target)`
+    pub bindmount_ro: Vec<(PathBuf, PathBuf)>,
+}
+
+impl Command {
+    pub fn new(bin: impl Into<PathBuf>) -> Self {
+        Self {
+            bin: bin.into(),
+            args: Vec::new(),
+            stdin: None,
+            stdout: None,
+            stderr: None,
+            bindmount_ro: Vec::new(),
+        }
+    }
+
+    pub fn arg(&mut self, arg: impl AsRef<OsStr>) -> &mut Self {
+        self.args.push(arg.as_ref().to_owned());
+        self
+    }
+
+    pub fn arg_if(&mut self, cond: bool, arg: impl AsRef<OsStr>) -> &mut Self {
+        if cond {
+            self.arg(arg);
+        }
+        self
+    }
+
+    pub fn stdio(
+        &mut self,
+        stdin: impl Into<PathBuf>,
+        stdout: impl Into<PathBuf>,
+        stderr: impl Into<PathBuf>,
+    ) -> &mut Self {
+        self.stdin = Some(stdin.into());
+        self.stdout = Some(stdout.into());
+        self.stderr = Some(stderr.into());
+        self
+    }
+
+    pub fn bindmount_ro(&mut self, src: impl Into<PathBuf>, dst: impl Into<PathBuf>) -> &mut Self {
+        self.bindmount_ro.push((src.into(), dst.into()));
+        self
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct SandboxOutput {
+    pub code: i32,
+    pub signal: i32,
+    pub real_time: u64, // milliseconds
+    pub user_time: u64, // milliseconds
+    pub sys_time: u64,  // milliseconds
+    pub memory: u64,    // KiB
+}
+
+impl SandboxOutput {
+    pub fn is_success(&self) -> bool {
+        self.code == 0 && self.signal == 0
+    }
+}
+
+/// the production backend, which needs root and cgroups
+pub struct Carapace {
+    uid: u32,
+    gid: u32,
+}
+
+impl Carapace {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            uid: config.executor.uid,
+            gid: config.executor.gid,
+        }
+    }
+}
+
+impl Sandbox for Carapace {
+    /// set chroot, uid, gid
+    ///
+    /// set real_time_limit, rlimits, cg_limits
+    ///
+    /// set priority
+    ///
+    /// set mount_proc, mount_tmpfs
+    ///
+    /// set seccomp_forbid_ipc
+    ///
+    /// add env PATH
+    ///
+    /// add some bindmount_rw and bindmount_ro;
+    fn run(&self, cmd: &Command, workspace: &Path, limit: &Limit) -> Result<SandboxOutput> {
+        let mut carapace_cmd = carapace::Command::new(&cmd.bin);
+        for arg in &cmd.args {
+            carapace_cmd.arg(arg);
+        }
+        if let (Some(stdin), Some(stdout), Some(stderr)) = (&cmd.stdin, &cmd.stdout, &cmd.stderr) {
+            carapace_cmd.stdio(stdin, stdout, stderr);
+        }
+
+        for (src, dst) in &cmd.bindmount_ro {
+            carapace_cmd.bindmount_ro(src, dst);
+        }
+
+        let bind_rw = ["/dev/null", "/dev/zero", "/dev/random", "/dev/urandom"];
+        let bind_ro = ["/lib", "/lib64"];
+        let set_bindmount = |s: &[&str], b: Vec<carapace::BindMount>| {
+            let iter1 = s
+                .iter()
+                .map(|src| carapace::BindMount::new_same(src.into()));
+            let iter2 = b.into_iter();
+            iter1.chain(iter2).collect::<Vec<_>>()
+        };
+
+        let c = &mut carapace_cmd.config;
+        c.chroot = Some(workspace.to_owned());
+        c.uid = Some(self.uid);
+        c.gid = Some(self.gid);
+        c.real_time_limit = Some(limit.real_time);
+        c.rlimit_cpu = Some(roundup_div(limit.cpu_time, 1000) as u32);
+        c.rlimit_fsize = Some(limit.output);
+        c.cg_limit_memory = Some(limit.memory);
+        c.cg_limit_max_pids = Some(limit.pids);
+        c.priority = Some(-20);
+        c.mount_proc = Some("/proc".into());
+        c.mount_tmpfs = Some("/tmp".into());
+        c.seccomp_forbid_ipc = true;
+
+        c.env.insert(
+            0,
+            "PATH=/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin:.".into(),
+        );
+
+        c.bindmount_rw = set_bindmount(&bind_rw, mem::take(&mut c.bindmount_rw));
+        c.bindmount_ro = set_bindmount(&bind_ro, mem::take(&mut c.bindmount_ro));
+
+        debug!(
+            "run embedded carapace:\n{:?}",
+            carapace_cmd.config.to_cli_cmd()
+        );
+        let output = carapace_cmd.run()?;
+        Ok(SandboxOutput {
+            code: output.code,
+            signal: output.signal,
+            real_time: output.real_time,
+            user_time: output.user_time,
+            sys_time: output.sys_time,
+            memory: output.memory,
+        })
+    }
+}
@@ -0,0 +1,184 @@
+use super::{Command, Sandbox, SandboxOutput};
+use crate::lang::Limit;
+
+use heng_utils::math::roundup_div;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use anyhow::Result;
+use nix::sys::signal::Signal;
+
+/// the scripted behavior of a program
+#[derive(Debug, Clone, Default)]
+pub struct MockRun {
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    /// files written to the workspace, such as executables and compile messages
+    pub files: Vec<(PathBuf, Vec<u8>)>,
+    pub code: i32,
+    pub signal: i32,
+    pub cpu_time: u64,          // milliseconds
+    pub real_time: Option<u64>, // milliseconds, the cpu time by default
+    pub memory: u64,            // bytes
+}
+
+impl MockRun {
+    pub fn exit(code: i32) -> Self {
+        Self {
+            code,
+            ..Self::default()
+        }
+    }
+
+    pub fn killed(signal: Signal) -> Self {
+        Self {
+            signal: signal as i32,
+            ..Self::default()
+        }
+    }
+
+    pub fn stdout(mut self, stdout: impl Into<Vec<u8>>) -> Self {
+        self.stdout = stdout.into();
+        self
+    }
+
+    pub fn stderr(mut self, stderr: impl Into<Vec<u8>>) -> Self {
+        self.stderr = stderr.into();
+        self
+    }
+
+    pub fn file(mut self, path: impl Into<PathBuf>, content: impl Into<Vec<u8>>) -> Self {
+        self.files.push((path.into(), content.into()));
+        self
+    }
+
+    pub fn cpu_time(mut self, cpu_time: u64) -> Self {
+        self.cpu_time = cpu_time;
+        self
+    }
+
+    pub fn real_time(mut self, real_time: u64) -> Self {
+        self.real_time = Some(real_time);
+        self
+    }
+
+    pub fn memory(mut self, memory: u64) -> Self {
+        self.memory = memory;
+        self
+    }
+}
+
+/// a command received by [`MockSandbox`]
+pub struct MockCall<'a> {
+    pub cmd: &'a Command,
+    pub workspace: &'a Path,
+    pub limit: &'a Limit,
+}
+
+impl MockCall<'_> {
+    /// resolves a path in the workspace, or `None` for devices
+    pub fn resolve(&self, path: &Path) -> Option<PathBuf> {
+        if path.starts_with("/dev") {
+            return None;
+        }
+        let relative = path.strip_prefix("/").unwrap_or(path);
+        Some(self.workspace.join(relative))
+    }
+
+    /// reads the stdin file, which is empty for devices
+    pub fn stdin(&self) -> Result<Vec<u8>> {
+        match self.cmd.stdin.as_deref().and_then(|p| self.resolve(p)) {
+            Some(path) => Ok(fs::read(path)?),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    pub fn args(&self) -> Vec<&str> {
+        self.cmd.args.iter().filter_map(|a| a.to_str()).collect()
+    }
+}
+
+type Handler = dyn Fn(&MockCall<'_>) -> Result<MockRun> + Send + Sync;
+
+/// a deterministic in-process sandbox for tests
+///
+/// Programs are scripted by a handler. Limits are applied to the scripted usage
+/// in the way of the real sandbox, so that limit hits can be simulated.
+pub struct MockSandbox {
+    handler: Box<Handler>,
+    calls: Mutex<Vec<Command>>,
+}
+
+impl MockSandbox {
+    pub fn new(handler: impl Fn(&MockCall<'_>) -> Result<MockRun> + Send + Sync + 'static) -> Self {
+        Self {
+            handler: Box::new(handler),
+            calls: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// commands received so far
+    pub fn calls(&self) -> Vec<Command> {
+        self.calls.lock().unwrap().clone()
+    }
+}
+
+impl Sandbox for MockSandbox {
+    fn run(&self, cmd: &Command, workspace: &Path, limit: &Limit) -> Result<SandboxOutput> {
+        self.calls.lock().unwrap().push(cmd.clone());
+
+        let call = MockCall {
+            cmd,
+            workspace,
+            limit,
+        };
+        let MockRun {
+            mut stdout,
+            stderr,
+            files,
+            code,
+            signal,
+            cpu_time,
+            real_time,
+            memory,
+        } = (self.handler)(&call)?;
+
+        let mut output = SandboxOutput {
+            code,
+            signal,
+            real_time: real_time.unwrap_or(cpu_time),
+            user_time: cpu_time,
+            sys_time: 0,
+            memory: roundup_div(memory, 1024),
+        };
+
+        if stdout.len() as u64 > limit.output {
+            stdout.truncate(limit.output as usize);
+            output.signal = Signal::SIGXFSZ as i32;
+        } else if memory >= limit.memory {
+            output.memory = roundup_div(limit.memory, 1024);
+            output.signal = Signal::SIGKILL as i32;
+        } else if cpu_time > limit.cpu_time {
+            output.signal = Signal::SIGXCPU as i32;
+        } else if output.real_time > limit.real_time {
+            output.signal = Signal::SIGKILL as i32;
+        }
+
+        let stdio = [(&cmd.stdout, stdout), (&cmd.stderr, stderr)];
+        let stdio = stdio
+            .iter()
+            .filter_map(|(path, content)| Some(((*path).as_deref()?, content)));
+        let files = files
+            .iter()
+            .map(|(path, content)| (path.as_path(), content));
+        for (path, content) in stdio.chain(files) {
+            if let Some(path) = call.resolve(path) {
+                fs::write(path, content)?;
+            }
+        }
+
+        Ok(output)
+    }
+}
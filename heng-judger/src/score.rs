@@ -0,0 +1,255 @@
+use heng_protocol::common::{
+    JudgeCaseResult, JudgeResultKind, Subtask, SubtaskResult, SubtaskScoring, TestPolicy,
+};
+use heng_protocol::error::ErrorCode;
+use heng_protocol::internal::ErrorInfo;
+
+use anyhow::Result;
+
+/// tracks which cases are still needed while judging subtasks
+pub struct SubtaskJudge<'a> {
+    subtasks: &'a [Subtask],
+    /// the subtasks containing each case
+    case_subtasks: Vec<Vec<usize>>,
+    /// whether a subtask has a case which is not fully passed
+    failed: Vec<bool>,
+}
+
+impl<'a> SubtaskJudge<'a> {
+    pub fn new(subtasks: &'a [Subtask], case_count: usize) -> Result<Self> {
+        let mut case_subtasks = vec![Vec::new(); case_count];
+        for (i, subtask) in subtasks.iter().enumerate() {
+            if subtask.cases.is_empty() {
+                reject_error!(
+                    ErrorCode::InvalidRequest,
+                    Some(format!("subtask {} has no cases", i))
+                )
+            }
+            for &case in &subtask.cases {
+                match case_subtasks.get_mut(case as usize) {
+                    Some(v) => v.push(i),
+                    None => reject_error!(
+                        ErrorCode::InvalidRequest,
+                        Some(format!("subtask {}: case {} is out of range", i, case))
+                    ),
+                }
+            }
+            for &dep in &subtask.dependencies {
+                if dep as usize >= i {
+                    reject_error!(
+                        ErrorCode::InvalidRequest,
+                        Some(format!(
+                            "subtask {}: dependency {} is not a previous subtask",
+                            i, dep
+                        ))
+                    )
+                }
+            }
+        }
+        Ok(Self {
+            subtasks,
+            case_subtasks,
+            failed: vec![false; subtasks.len()],
+        })
+    }
+
+    /// returns whether a case still affects the score of any subtask
+    pub fn needs(&self, case: usize) -> bool {
+        let blocked = blocked(self.subtasks, &self.failed);
+        self.case_subtasks[case].iter().any(|&i| {
+            let fused = matches!(self.subtasks[i].policy, TestPolicy::Fuse) && self.failed[i];
+            !blocked[i] && !fused
+        })
+    }
+
+    pub fn update(&mut self, case: usize, result: &JudgeCaseResult) {
+        if case_score(result) < 1.0 {
+            for &i in &self.case_subtasks[case] {
+                self.failed[i] = true;
+            }
+        }
+    }
+}
+
+/// a subtask is blocked if any of its dependencies is not fully passed
+fn blocked(subtasks: &[Subtask], failed: &[bool]) -> Vec<bool> {
+    let mut blocked = vec![false; subtasks.len()];
+    for (i, subtask) in subtasks.iter().enumerate() {
+        blocked[i] = subtask.dependencies.iter().any(|&dep| {
+            let dep = dep as usize;
+            failed[dep] || blocked[dep]
+        });
+    }
+    blocked
+}
+
+pub fn case_score(result: &JudgeCaseResult) -> f64 {
+    match result.score {
+        Some(score) => score.clamp(0.0, 1.0),
+        None if result.kind == JudgeResultKind::Accepted => 1.0,
+        None => 0.0,
+    }
+}
+
+/// the total score of a test without subtasks, out of 100
+///
+/// cases which are not judged are counted as zero.
+pub fn aggregate(cases: &[JudgeCaseResult], case_count: usize) -> f64 {
+    if case_count == 0 {
+        return 0.0;
+    }
+    let sum: f64 = cases.iter().map(case_score).sum();
+    sum * 100.0 / case_count as f64
+}
+
+/// evaluates subtask results and the total score
+///
+/// `cases` must contain a result for every case, including skipped ones.
+pub fn evaluate(subtasks: &[Subtask], cases: &[JudgeCaseResult]) -> (Vec<SubtaskResult>, f64) {
+    let failed: Vec<bool> = subtasks
+        .iter()
+        .map(|s| {
+            s.cases
+                .iter()
+                .any(|&c| case_score(&cases[c as usize]) < 1.0)
+        })
+        .collect();
+    let blocked = blocked(subtasks, &failed);
+
+    let results: Vec<SubtaskResult> = subtasks
+        .iter()
+        .zip(blocked)
+        .map(|(subtask, blocked)| {
+            if blocked {
+                return SubtaskResult {
+                    kind: JudgeResultKind::Skipped,
+                    score: 0.0,
+                };
+            }
+
+            let case_results = subtask.cases.iter().map(|&c| &cases[c as usize]);
+
+            let kind = case_results
+                .clone()
+                .map(|r| r.kind)
+                .find(|&kind| kind != JudgeResultKind::Accepted)
+                .unwrap_or(JudgeResultKind::Accepted);
+
+            let scores = case_results.map(case_score);
+            let ratio = match subtask.scoring {
+                SubtaskScoring::Min => scores.fold(1.0, f64::min),
+                SubtaskScoring::Sum => scores.sum::<f64>() / subtask.cases.len() as f64,
+            };
+
+            SubtaskResult {
+                kind,
+                score: subtask.score * ratio,
+            }
+        })
+        .collect();
+
+    let total = results.iter().map(|r| r.score).sum();
+    (results, total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subtask(cases: &[u32], score: f64, fuse: bool, min: bool, deps: &[u32]) -> Subtask {
+        Subtask {
+            cases: cases.to_vec(),
+            score,
+            policy: if fuse {
+                TestPolicy::Fuse
+            } else {
+                TestPolicy::All
+            },
+            scoring: if min {
+                SubtaskScoring::Min
+            } else {
+                SubtaskScoring::Sum
+            },
+            dependencies: deps.to_vec(),
+        }
+    }
+
+    fn case(kind: JudgeResultKind) -> JudgeCaseResult {
+        JudgeCaseResult {
+            kind,
+            time: 0,
+            memory: 0,
+            score: None,
+            message: None,
+            diagnostics: None,
+        }
+    }
+
+    #[test]
+    fn needs_and_evaluate() {
+        use JudgeResultKind::*;
+
+        let subtasks = vec![
+            subtask(&[0, 1], 20.0, true, true, &[]),
+            subtask(&[2, 3], 30.0, false, false, &[]),
+            subtask(&[1, 4], 50.0, true, true, &[0]),
+        ];
+        let mut judge = SubtaskJudge::new(&subtasks, 5).unwrap();
+
+        let verdicts = [Accepted, WrongAnswer, Accepted, TimeLimitExceeded, Accepted];
+        let mut cases = Vec::new();
+        for (i, &kind) in verdicts.iter().enumerate() {
+            if judge.needs(i) {
+                let result = case(kind);
+                judge.update(i, &result);
+                cases.push(result);
+            } else {
+                cases.push(case(Skipped));
+            }
+        }
+        // subtask 2 is blocked by subtask 0
+        assert_eq!(cases[4].kind, Skipped);
+
+        let (results, total) = evaluate(&subtasks, &cases);
+        assert_eq!(results[0].kind, WrongAnswer);
+        assert_eq!(results[0].score, 0.0);
+        assert_eq!(results[1].kind, TimeLimitExceeded);
+        assert_eq!(results[1].score, 15.0);
+        assert_eq!(results[2].kind, Skipped);
+        assert_eq!(total, 15.0);
+    }
+
+    #[test]
+    fn partial_scores() {
+        use JudgeResultKind::*;
+
+        let mut partial = case(PartiallyCorrect);
+        partial.score = Some(0.5);
+        let cases = vec![case(Accepted), partial, case(WrongAnswer), case(Accepted)];
+
+        assert_eq!(aggregate(&cases, 4), 62.5);
+        assert_eq!(aggregate(&cases[..2], 4), 37.5);
+
+        let subtasks = vec![
+            subtask(&[0, 1], 40.0, false, false, &[]),
+            subtask(&[1, 3], 60.0, false, true, &[]),
+        ];
+        let (results, total) = evaluate(&subtasks, &cases);
+        assert_eq!(results[0].kind, PartiallyCorrect);
+        assert_eq!(results[0].score, 30.0);
+        assert_eq!(results[1].score, 30.0);
+        assert_eq!(total, 60.0);
+    }
+
+    #[test]
+    fn invalid_subtasks() {
+        let out_of_range = vec![subtask(&[0, 3], 10.0, true, true, &[])];
+        assert!(SubtaskJudge::new(&out_of_range, 3).is_err());
+
+        let forward_dep = vec![
+            subtask(&[0], 10.0, true, true, &[1]),
+            subtask(&[1], 10.0, true, true, &[]),
+        ];
+        assert!(SubtaskJudge::new(&forward_dep, 2).is_err());
+    }
+}
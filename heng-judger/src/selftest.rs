@@ -0,0 +1,309 @@
+use crate::exec::ExecutorModule;
+use crate::lang::{self, Limit};
+use crate::sandbox::Sandbox;
+use crate::Config;
+
+use heng_protocol::common::Environment;
+use heng_utils::container::inject;
+
+use std::fmt::Write;
+use std::fs;
+use std::time::Instant;
+
+use anyhow::{Context, Result};
+use nix::unistd::{self, Gid, Uid};
+use serde::Serialize;
+use serde_json::Map;
+use tokio::task;
+use tracing::{error, info, warn};
+
+const EXPECTED_OUTPUT: &str = "hello\n";
+
+/// the result of running a hello-world program
+#[derive(Debug, Clone, Serialize)]
+pub struct LanguageReport {
+    pub language: &'static str,
+    pub passed: bool,
+    pub compile_time: Option<u64>, // in milliseconds
+    pub run_time: Option<u64>,     // in milliseconds
+    pub error: Option<String>,
+}
+
+/// runs the self-test, then disables failing languages
+pub async fn apply() -> Result<Vec<LanguageReport>> {
+    let config = inject::<Config>();
+    let executor = inject::<ExecutorModule>();
+    let sandbox = executor.sandbox().clone();
+    let reports = task::spawn_blocking(move || run(&config, &*sandbox)).await?;
+    disable_failed(&executor, &reports);
+    Ok(reports)
+}
+
+/// disables languages which failed, so that they are not advertised to the controller
+fn disable_failed(executor: &ExecutorModule, reports: &[LanguageReport]) {
+    let failed = reports.iter().filter(|r| !r.passed);
+    executor.disable_languages(failed.map(|r| r.language.to_owned()));
+}
+
+/// compiles and runs a hello-world program of every language
+pub fn run(config: &Config, sandbox: &dyn Sandbox) -> Vec<LanguageReport> {
+    lang::LANGUAGES
+        .iter()
+        .map(|language| {
+            let report = test_language(config, sandbox, language);
+            match report.error {
+                None => info!(
+                    language,
+                    compile_time = ?report.compile_time,
+                    run_time = ?report.run_time,
+                    "self-test passed"
+                ),
+                Some(ref err) => error!(language, %err, "self-test failed, language is disabled"),
+            }
+            report
+        })
+        .collect()
+}
+
+fn test_language(config: &Config, sandbox: &dyn Sandbox, language: &'static str) -> LanguageReport {
+    let mut report = LanguageReport {
+        language,
+        passed: false,
+        compile_time: None,
+        run_time: None,
+        error: None,
+    };
+    match check(config, sandbox, &mut report) {
+        Ok(()) => report.passed = true,
+        Err(err) => report.error = Some(format!("{:#}", err)),
+    }
+    report
+}
+
+fn check(config: &Config, sandbox: &dyn Sandbox, report: &mut LanguageReport) -> Result<()> {
+    let environment = Environment {
+        language: report.language.to_owned(),
+        system: std::env::consts::OS.to_owned(),
+        arch: std::env::consts::ARCH.to_owned(),
+        options: Map::new(),
+    };
+    let lang = lang::from_environment(&environment)?;
+
+    let workspace = config
+        .executor
+        .workspace_root
+        .join(format!("__selftest_{}", report.language));
+    if workspace.exists() {
+        fs::remove_dir_all(&workspace)?;
+    }
+    fs::create_dir_all(&workspace)?;
+    let _guard = scopeguard::guard(workspace.clone(), |workspace| {
+        if let Err(err) = fs::remove_dir_all(&workspace) {
+            warn!(%err, workspace = %workspace.display(), "failed to remove workspace");
+        }
+    });
+    unistd::chown(
+        &workspace,
+        Some(Uid::from_raw(config.executor.uid)),
+        Some(Gid::from_raw(config.executor.gid)),
+    )?;
+
+    fs::write(
+        workspace.join(lang.src_name()),
+        source_code(report.language),
+    )?;
+
+    let hard_limit = &config.executor.hard_limit;
+    let limit = Limit {
+        real_time: 10000,
+        cpu_time: 5000,
+        memory: hard_limit.memory.as_u64(),
+        output: hard_limit.output.as_u64(),
+        pids: hard_limit.pids,
+    };
+
+    if lang.needs_compile() {
+        let t0 = Instant::now();
+        let output = lang
+            .compile(sandbox, workspace.clone(), &limit)
+            .context("failed to compile")?;
+        report.compile_time = Some(t0.elapsed().as_millis() as u64);
+        if !output.is_success() {
+            let message = fs::read_to_string(workspace.join(lang.msg_name())).unwrap_or_default();
+            anyhow::bail!("compile error: {:?}\n{}", output, message.trim_end());
+        }
+    }
+
+    let t0 = Instant::now();
+    let output = lang
+        .run(
+            sandbox,
+            workspace.clone(),
+            "/dev/null".into(),
+            "__user_out".into(),
+            "__user_err".into(),
+            &limit,
+        )
+        .context("failed to run")?;
+    report.run_time = Some(t0.elapsed().as_millis() as u64);
+    if !output.is_success() {
+        let stderr = fs::read_to_string(workspace.join("__user_err")).unwrap_or_default();
+        anyhow::bail!("runtime error: {:?}\n{}", output, stderr.trim_end());
+    }
+
+    let stdout = fs::read_to_string(workspace.join("__user_out"))?;
+    if stdout != EXPECTED_OUTPUT {
+        anyhow::bail!("unexpected output: {:?}", stdout);
+    }
+    Ok(())
+}
+
+fn source_code(language: &str) -> &'static str {
+    match language {
+        "c" => "#include <stdio.h>\nint main() { printf(\"hello\\n\"); return 0; }\n",
+        "cpp" => "#include <iostream>\nint main() { std::cout << \"hello\" << std::endl; }\n",
+        "java" => concat!(
+            "public class Main {\n",
+            "    public static void main(String[] args) { System.out.println(\"hello\"); }\n",
+            "}\n"
+        ),
+        "javascript" => "console.log(\"hello\")\n",
+        "python" => "print(\"hello\")\n",
+        "rust" => "fn main() { println!(\"hello\"); }\n",
+        _ => unreachable!("no self-test program: {}", language),
+    }
+}
+
+/// formats reports as a plain text table
+pub fn format_table(reports: &[LanguageReport]) -> String {
+    let format_time = |time: Option<u64>| match time {
+        Some(time) => format!("{}ms", time),
+        None => "-".to_owned(),
+    };
+
+    let mut table = String::new();
+    let _ = writeln!(
+        table,
+        "{:<12} {:<6} {:>10} {:>10}",
+        "language", "result", "compile", "run"
+    );
+    for report in reports {
+        let _ = writeln!(
+            table,
+            "{:<12} {:<6} {:>10} {:>10}",
+            report.language,
+            if report.passed { "pass" } else { "fail" },
+            format_time(report.compile_time),
+            format_time(report.run_time),
+        );
+        if let Some(ref err) = report.error {
+            for line in err.lines() {
+                let _ = writeln!(table, "    {}", line);
+            }
+        }
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exec::tests::executor;
+    use crate::login;
+    use crate::profile::Profile;
+    use crate::sandbox::mock::{MockCall, MockRun, MockSandbox};
+
+    fn profile() -> Profile {
+        Profile {
+            instance_id: "selftest".to_owned(),
+            name: None,
+            max_concurrency: 1,
+            core_count: 1,
+            software: String::new(),
+        }
+    }
+
+    /// runs the self-test against a scripted sandbox and returns the advertised languages
+    fn advertised(sandbox: MockSandbox) -> (Vec<LanguageReport>, Vec<String>) {
+        let (_dir, config, executor) = executor(sandbox);
+        let reports = run(&config, &**executor.sandbox());
+        disable_failed(&executor, &reports);
+        let request = login::token_request(&profile(), &[], &executor.languages());
+        (reports, request.languages.unwrap())
+    }
+
+    fn is_source(call: &MockCall<'_>, name: &str) -> bool {
+        call.args().iter().any(|arg| arg.ends_with(name))
+    }
+
+    #[test]
+    fn compile_failure() {
+        let (reports, languages) = advertised(MockSandbox::new(|call| {
+            if is_source(call, "src.cpp") {
+                return Ok(MockRun::exit(1).stderr("src.cpp:1:1: error: expected ';'"));
+            }
+            Ok(MockRun::exit(0).stdout(EXPECTED_OUTPUT))
+        }));
+
+        let cpp = reports.iter().find(|r| r.language == "cpp").unwrap();
+        assert!(!cpp.passed);
+        assert!(cpp.error.as_deref().unwrap().starts_with("compile error"));
+        assert!(!languages.iter().any(|l| l == "cpp"));
+        assert!(languages.iter().any(|l| l == "c"));
+        assert_eq!(languages.len(), lang::LANGUAGES.len() - 1);
+    }
+
+    #[test]
+    fn wrong_output() {
+        let (reports, languages) = advertised(MockSandbox::new(|call| {
+            if is_source(call, "src.py") {
+                return Ok(MockRun::exit(0).stdout("hallo\n"));
+            }
+            Ok(MockRun::exit(0).stdout(EXPECTED_OUTPUT))
+        }));
+
+        let python = reports.iter().find(|r| r.language == "python").unwrap();
+        assert!(!python.passed);
+        assert!(python
+            .error
+            .as_deref()
+            .unwrap()
+            .starts_with("unexpected output"));
+        assert!(!languages.iter().any(|l| l == "python"));
+        assert_eq!(languages.len(), lang::LANGUAGES.len() - 1);
+    }
+
+    #[test]
+    fn programs() {
+        for language in lang::LANGUAGES {
+            assert!(!source_code(language).is_empty());
+        }
+    }
+
+    #[test]
+    fn table() {
+        let reports = [
+            LanguageReport {
+                language: "cpp",
+                passed: true,
+                compile_time: Some(420),
+                run_time: Some(3),
+                error: None,
+            },
+            LanguageReport {
+                language: "java",
+                passed: false,
+                compile_time: None,
+                run_time: None,
+                error: Some("failed to compile: no such file".to_owned()),
+            },
+        ];
+        let table = format_table(&reports);
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[1].starts_with("cpp") && lines[1].contains("pass"));
+        assert!(lines[1].contains("420ms") && lines[1].ends_with("3ms"));
+        assert!(lines[2].starts_with("java") && lines[2].contains("fail"));
+        assert_eq!(lines[3].trim(), "failed to compile: no such file");
+    }
+}
@@ -0,0 +1,177 @@
+use heng_protocol::common::{CpuHardwareStatus, HarewareStatus, MemoryHardwareStatus};
+
+use std::fs;
+use std::sync::Mutex;
+
+use anyhow::{format_err, Result};
+
+pub struct StatusCollector {
+    last_cpu_times: Mutex<Option<CpuTimes>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct CpuTimes {
+    busy: u64,
+    total: u64,
+}
+
+impl StatusCollector {
+    pub fn new() -> Self {
+        Self {
+            last_cpu_times: Mutex::new(None),
+        }
+    }
+
+    pub fn collect(&self) -> Result<HarewareStatus> {
+        let cpu_times = parse_cpu_times(&fs::read_to_string("/proc/stat")?)?;
+
+        let cpu_percentage = {
+            let mut last = self.last_cpu_times.lock().unwrap();
+            // the first sample is measured since boot
+            let prev = last
+                .replace(cpu_times)
+                .unwrap_or(CpuTimes { busy: 0, total: 0 });
+            let busy = cpu_times.busy.saturating_sub(prev.busy);
+            let total = cpu_times.total.saturating_sub(prev.total);
+            to_percentage(busy, total)
+        };
+
+        Ok(HarewareStatus {
+            cpu: CpuHardwareStatus {
+                percentage: cpu_percentage,
+                loadavg: Some(parse_loadavg(&fs::read_to_string("/proc/loadavg")?)?),
+            },
+            memory: MemoryHardwareStatus {
+                percentage: parse_memory_percentage(&fs::read_to_string("/proc/meminfo")?)?,
+            },
+        })
+    }
+}
+
+fn to_percentage(part: u64, total: u64) -> u8 {
+    if total == 0 {
+        return 0;
+    }
+    (part.min(total) * 100 / total) as u8
+}
+
+/// parses the aggregated "cpu" line of /proc/stat
+fn parse_cpu_times(content: &str) -> Result<CpuTimes> {
+    let line = content
+        .lines()
+        .find(|line| line.starts_with("cpu "))
+        .ok_or_else(|| format_err!("invalid /proc/stat"))?;
+
+    // user nice system idle iowait irq softirq steal guest guest_nice
+    let fields = line
+        .split_ascii_whitespace()
+        .skip(1)
+        .map(|s| s.parse::<u64>())
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if fields.len() < 4 {
+        anyhow::bail!("invalid /proc/stat")
+    }
+
+    // guest time is already accounted in user time
+    let total: u64 = fields.iter().take(8).sum();
+    let idle = fields[3] + fields.get(4).copied().unwrap_or(0);
+
+    Ok(CpuTimes {
+        busy: total - idle,
+        total,
+    })
+}
+
+fn parse_loadavg(content: &str) -> Result<[f32; 3]> {
+    let mut iter = content.split_ascii_whitespace();
+    let mut loadavg = [0.0; 3];
+    for x in loadavg.iter_mut() {
+        let s = iter
+            .next()
+            .ok_or_else(|| format_err!("invalid /proc/loadavg"))?;
+        *x = s.parse()?;
+    }
+    Ok(loadavg)
+}
+
+fn parse_memory_percentage(content: &str) -> Result<u8> {
+    let mut total = None;
+    let mut available = None;
+    for line in content.lines() {
+        let mut iter = line.split_ascii_whitespace();
+        let slot = match iter.next() {
+            Some("MemTotal:") => &mut total,
+            Some("MemAvailable:") => &mut available,
+            _ => continue,
+        };
+        *slot = iter.next().map(|s| s.parse::<u64>()).transpose()?;
+    }
+
+    match (total, available) {
+        (Some(total), Some(available)) => Ok(to_percentage(total.saturating_sub(available), total)),
+        _ => Err(format_err!("invalid /proc/meminfo")),
+    }
+}
+
+/// total memory in bytes
+pub fn read_memory_total() -> Result<u64> {
+    parse_memory_total(&fs::read_to_string("/proc/meminfo")?)
+}
+
+fn parse_memory_total(content: &str) -> Result<u64> {
+    let line = content
+        .lines()
+        .find(|line| line.starts_with("MemTotal:"))
+        .ok_or_else(|| format_err!("invalid /proc/meminfo"))?;
+    let kib = line
+        .split_ascii_whitespace()
+        .nth(1)
+        .ok_or_else(|| format_err!("invalid /proc/meminfo"))?
+        .parse::<u64>()?;
+    Ok(kib * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MEMINFO: &str = "\
+MemTotal:        8048480 kB
+MemFree:          348476 kB
+MemAvailable:    2012120 kB
+Buffers:          215112 kB
+";
+
+    #[test]
+    fn cpu_times() {
+        let stat = "\
+cpu  10132153 290696 3084719 46828483 16683 0 25195 0 175628 0
+cpu0 1393280 32966 572056 13343292 6130 0 17875 0 23933 0
+intr 1462898 0 0 0
+";
+        let times = parse_cpu_times(stat).unwrap();
+        assert_eq!(times.total, 60377929);
+        assert_eq!(times.busy, 60377929 - 46828483 - 16683);
+
+        assert!(parse_cpu_times("cpu0 1 2 3 4\n").is_err());
+        assert!(parse_cpu_times("cpu  1 2 3\n").is_err());
+        assert!(parse_cpu_times("cpu  1 2 x 4\n").is_err());
+    }
+
+    #[test]
+    fn loadavg() {
+        let loadavg = parse_loadavg("0.52 1.05 2.50 3/1024 12345\n").unwrap();
+        assert_eq!(loadavg, [0.52, 1.05, 2.5]);
+        assert!(parse_loadavg("0.52 1.05\n").is_err());
+    }
+
+    #[test]
+    fn memory() {
+        assert_eq!(parse_memory_percentage(MEMINFO).unwrap(), 75);
+        assert_eq!(parse_memory_total(MEMINFO).unwrap(), 8048480 * 1024);
+        assert!(parse_memory_percentage("MemTotal: 1024 kB\n").is_err());
+        assert!(parse_memory_total("MemFree: 1024 kB\n").is_err());
+        assert_eq!(to_percentage(1, 0), 0);
+    }
+}
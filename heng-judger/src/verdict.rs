@@ -0,0 +1,179 @@
+use crate::lang::Limit;
+use crate::sandbox::SandboxOutput;
+
+use heng_protocol::common::JudgeResultKind;
+
+use nix::sys::signal::Signal;
+
+/// cpu time in milliseconds
+pub fn cpu_time(output: &SandboxOutput) -> u64 {
+    output.user_time + output.sys_time
+}
+
+/// memory in bytes
+pub fn memory(output: &SandboxOutput) -> u64 {
+    // sandboxes report memory in KiB
+    output.memory * 1024
+}
+
+/// returns `None` if the program exits normally within limits
+pub fn runtime_verdict(output: &SandboxOutput, limit: &Limit) -> Option<JudgeResultKind> {
+    if output.signal == Signal::SIGXFSZ as i32 {
+        return Some(JudgeResultKind::OutputLimitExceeded);
+    }
+    if memory(output) >= limit.memory {
+        return Some(JudgeResultKind::MemoryLimitExceeded);
+    }
+    if output.signal == Signal::SIGXCPU as i32
+        || cpu_time(output) > limit.cpu_time
+        || output.real_time > limit.real_time
+    {
+        return Some(JudgeResultKind::TimeLimitExceeded);
+    }
+    if output.signal != 0 || output.code != 0 {
+        return Some(JudgeResultKind::RuntimeError);
+    }
+    None
+}
+
+/// the verdict of a failed compilation
+pub fn compile_verdict(output: &SandboxOutput, limit: &Limit) -> JudgeResultKind {
+    match runtime_verdict(output, limit) {
+        Some(JudgeResultKind::OutputLimitExceeded) => JudgeResultKind::CompileFileLimitExceeded,
+        Some(JudgeResultKind::MemoryLimitExceeded) => JudgeResultKind::CompileMemoryLimitExceeded,
+        Some(JudgeResultKind::TimeLimitExceeded) => JudgeResultKind::CompileTimeLimitExceeded,
+        _ => JudgeResultKind::CompileError,
+    }
+}
+
+/// the verdict and the score ratio of a special judge or an interactor,
+/// which follows testlib exit codes
+///
+/// `message` is the output of the checker, which carries the points of `_points`.
+pub fn checker_verdict(
+    output: &SandboxOutput,
+    limit: &Limit,
+    message: &str,
+) -> (JudgeResultKind, Option<f64>) {
+    let kind = match runtime_verdict(output, limit) {
+        None => return (JudgeResultKind::Accepted, Some(1.0)),
+        Some(JudgeResultKind::RuntimeError) if output.signal == 0 => match output.code {
+            // _wa, _pe
+            1 | 2 => return (JudgeResultKind::WrongAnswer, Some(0.0)),
+            // _fail
+            3 => JudgeResultKind::SystemError,
+            // _points
+            7 => match parse_points(message) {
+                Some(points) => return partial_verdict(points),
+                None => JudgeResultKind::SystemError,
+            },
+            // _pc(percent)
+            code @ 16..=116 => return partial_verdict(f64::from(code - 16) / 100.0),
+            _ => JudgeResultKind::SystemRuntimeError,
+        },
+        Some(JudgeResultKind::OutputLimitExceeded) => JudgeResultKind::SystemOutputLimitExceeded,
+        Some(JudgeResultKind::MemoryLimitExceeded) => JudgeResultKind::SystemMemoryLimitExceeded,
+        Some(JudgeResultKind::TimeLimitExceeded) => JudgeResultKind::SystemTimeLimitExceeded,
+        Some(_) => JudgeResultKind::SystemRuntimeError,
+    };
+    (kind, None)
+}
+
+fn partial_verdict(ratio: f64) -> (JudgeResultKind, Option<f64>) {
+    let ratio = ratio.clamp(0.0, 1.0);
+    let kind = if ratio >= 1.0 {
+        JudgeResultKind::Accepted
+    } else if ratio <= 0.0 {
+        JudgeResultKind::WrongAnswer
+    } else {
+        JudgeResultKind::PartiallyCorrect
+    };
+    (kind, Some(ratio))
+}
+
+/// parses `points <ratio> <message>` or `<ratio> <message>`
+fn parse_points(message: &str) -> Option<f64> {
+    let mut iter = message.split_ascii_whitespace().peekable();
+    if iter.peek() == Some(&"points") {
+        iter.next();
+    }
+    let points: f64 = iter.next()?.parse().ok()?;
+    Some(points).filter(|p| p.is_finite())
+}
+
+/// compares outputs line by line, ignoring trailing whitespaces and trailing empty lines
+///
+/// returns the first differing line number (starts from 1) and the trimmed lines
+pub fn first_difference<'a>(user: &'a [u8], answer: &'a [u8]) -> Option<(u64, &'a [u8], &'a [u8])> {
+    fn lines(s: &[u8]) -> Vec<&[u8]> {
+        let mut lines: Vec<&[u8]> = s.split(|&b| b == b'\n').map(trim_end).collect();
+        while let Some(&[]) = lines.last() {
+            lines.pop();
+        }
+        lines
+    }
+
+    fn trim_end(mut s: &[u8]) -> &[u8] {
+        while let [rest @ .., last] = s {
+            if !last.is_ascii_whitespace() {
+                break;
+            }
+            s = rest;
+        }
+        s
+    }
+
+    let user = lines(user);
+    let answer = lines(answer);
+    for i in 0..user.len().max(answer.len()) {
+        let u = user.get(i).copied().unwrap_or_default();
+        let a = answer.get(i).copied().unwrap_or_default();
+        if u != a {
+            return Some((i as u64 + 1, u, a));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn points() {
+        assert_eq!(parse_points("points 0.25 half of half"), Some(0.25));
+        assert_eq!(parse_points("0.5\n"), Some(0.5));
+        assert_eq!(parse_points("points"), None);
+        assert_eq!(parse_points("wrong answer"), None);
+        assert_eq!(parse_points("points NaN"), None);
+
+        assert_eq!(
+            partial_verdict(0.5),
+            (JudgeResultKind::PartiallyCorrect, Some(0.5))
+        );
+        assert_eq!(partial_verdict(1.5), (JudgeResultKind::Accepted, Some(1.0)));
+        assert_eq!(
+            partial_verdict(0.0),
+            (JudgeResultKind::WrongAnswer, Some(0.0))
+        );
+    }
+
+    #[test]
+    fn compare() {
+        fn compare_output(user: &[u8], answer: &[u8]) -> bool {
+            first_difference(user, answer).is_none()
+        }
+
+        assert!(compare_output(b"1 2\n3\n", b"1 2\n3"));
+        assert!(compare_output(b"1 2  \r\n3\n\n\n", b"1 2\n3\n"));
+        assert!(compare_output(b"", b"\n\n"));
+        assert!(!compare_output(b"1 2\n3\n", b"1  2\n3\n"));
+        assert!(!compare_output(b"1\n\n2\n", b"1\n2\n"));
+        assert!(!compare_output(b" 1\n", b"1\n"));
+
+        let diff = first_difference(b"1\n2 \n3\n", b"1\n2\n4 5\n");
+        assert_eq!(diff, Some((3, &b"3"[..], &b"4 5"[..])));
+        let diff = first_difference(b"1\n", b"1\n2\n");
+        assert_eq!(diff, Some((2, &b""[..], &b"2"[..])));
+    }
+}
@@ -4,6 +4,7 @@ use heng_judger::lang::javascript::JavaScript;
 use heng_judger::lang::python::Python;
 use heng_judger::lang::rust::Rust;
 use heng_judger::lang::Language;
+use heng_judger::sandbox::Carapace;
 use heng_judger::{lang, Config};
 
 use heng_utils::container::{inject, Container};
@@ -55,6 +56,8 @@ fn test_lang(
         Some(Gid::from_raw(config.executor.gid)),
     )?;
 
+    let sandbox = Carapace::new(&config);
+
     let src_path = workspace.join(lang.src_name());
 
     fs::write(&src_path, source_code)?;
@@ -70,7 +73,7 @@ fn test_lang(
     let t1 = Instant::now();
     if lang.needs_compile() {
         let compile_output = lang
-            .compile(workspace.clone(), &compile_limit)
+            .compile(&sandbox, workspace.clone(), &compile_limit)
             .context("failed to compile code")?;
 
         debug!(name=?lang.lang_name(), ?compile_output);
@@ -93,6 +96,7 @@ fn test_lang(
     let t2 = Instant::now();
     let sandbox_output = lang
         .run(
+            &sandbox,
             workspace.clone(),
             "/dev/null".into(),
             "__user_out".into(),
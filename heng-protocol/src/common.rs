@@ -1,3 +1,5 @@
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
 use serde_json::{Map, Value};
 
@@ -8,6 +10,12 @@ pub enum File {
     Url {
         url: String,
         hashsum: Option<String>,
+        /// extra request headers
+        #[serde(default)]
+        headers: Option<BTreeMap<String, String>>,
+        /// sent as `Authorization: Bearer <token>`
+        #[serde(default)]
+        token: Option<String>,
     },
     #[serde(rename = "direct")]
     Direct {
@@ -15,6 +23,57 @@ pub enum File {
         hashsum: Option<String>,
         base64: bool,
     },
+    /// raw bytes, only produced for binary codecs and rejected by the external api
+    #[serde(rename = "binary")]
+    Binary {
+        #[serde(with = "serde_bytes")]
+        content: Vec<u8>,
+        hashsum: Option<String>,
+    },
+    /// uploaded to the file store of the controller
+    #[serde(rename = "stored")]
+    Stored { hashsum: String },
+}
+
+impl File {
+    /// decodes base64 content into raw bytes
+    pub fn into_binary(self) -> Self {
+        match self {
+            File::Direct {
+                content,
+                hashsum,
+                base64: true,
+            } => match base64::decode(&content) {
+                Ok(content) => File::Binary { content, hashsum },
+                Err(_) => File::Direct {
+                    content,
+                    hashsum,
+                    base64: true,
+                },
+            },
+            File::Direct {
+                content,
+                hashsum,
+                base64: false,
+            } => File::Binary {
+                content: content.into_bytes(),
+                hashsum,
+            },
+            file => file,
+        }
+    }
+
+    /// `{algorithm}:{hex}` of sha256, blake3 or md5, or bare sha256 hex
+    ///
+    /// Md5 only verifies url and direct files. Stored files are addressed by sha256 or blake3.
+    pub fn hashsum(&self) -> Option<&str> {
+        match self {
+            File::Url { hashsum, .. } => hashsum.as_deref(),
+            File::Direct { hashsum, .. } => hashsum.as_deref(),
+            File::Binary { hashsum, .. } => hashsum.as_deref(),
+            File::Stored { hashsum } => Some(hashsum),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -42,7 +101,35 @@ pub struct TestCase {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Test {
     pub cases: Vec<TestCase>,
+    /// ignored when `subtasks` is given
+    pub policy: TestPolicy,
+    #[serde(default)]
+    pub subtasks: Option<Vec<Subtask>>,
+    /// discovers cases in the data directory, which is implied by empty `cases`
+    #[serde(default)]
+    pub discover: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SubtaskScoring {
+    /// score * min(case scores)
+    Min,
+    /// score * avg(case scores)
+    Sum,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Subtask {
+    /// indices of `Test::cases`
+    pub cases: Vec<u32>,
+    pub score: f64,
+    /// the policy of cases in this subtask
     pub policy: TestPolicy,
+    pub scoring: SubtaskScoring,
+    /// indices of previous subtasks which must be fully passed
+    #[serde(default)]
+    pub dependencies: Vec<u32>,
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -80,6 +167,7 @@ pub struct Limit {
 pub struct Executable {
     pub source: File,
     pub environment: Environment,
+    #[serde(default)]
     pub limit: Limit,
 }
 
@@ -94,10 +182,47 @@ pub enum Judge {
     Interactive {
         user: Executable,
         interactor: Executable,
+        /// checks the output of the interactor
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        checker: Option<Executable>,
     },
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl Judge {
+    pub fn executables(&self) -> Vec<&Executable> {
+        match self {
+            Judge::Normal { user } => vec![user],
+            Judge::Special { user, spj } => vec![user, spj],
+            Judge::Interactive {
+                user,
+                interactor,
+                checker,
+            } => {
+                let mut executables = vec![user, interactor];
+                executables.extend(checker);
+                executables
+            }
+        }
+    }
+
+    pub fn executables_mut(&mut self) -> Vec<&mut Executable> {
+        match self {
+            Judge::Normal { user } => vec![user],
+            Judge::Special { user, spj } => vec![user, spj],
+            Judge::Interactive {
+                user,
+                interactor,
+                checker,
+            } => {
+                let mut executables = vec![user, interactor];
+                executables.extend(checker);
+                executables
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct JudgeStatus {
     pub pending: u32,
     pub preparing: u32,
@@ -105,23 +230,39 @@ pub struct JudgeStatus {
     pub finished: u32,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CpuHardwareStatus {
     pub percentage: u8,
-    pub loadavg: Option<[u8; 3]>,
+    pub loadavg: Option<[f32; 3]>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MemoryHardwareStatus {
     pub percentage: u8,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HarewareStatus {
     pub cpu: CpuHardwareStatus,
     pub memory: MemoryHardwareStatus,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatusReport {
+    pub hardware: HarewareStatus,
+    pub judge: JudgeStatus,
+    #[serde(default)]
+    pub data: Option<DataCacheStatus>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DataCacheStatus {
+    /// hashsums of cached data, such as `sha256:{hex}`
+    pub hashsums: Vec<String>,
+    pub hits: u64,
+    pub misses: u64,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum JudgeState {
@@ -132,10 +273,11 @@ pub enum JudgeState {
     Finished,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum JudgeResultKind {
     Accepted,
     WrongAnswer,
+    PartiallyCorrect,
 
     RuntimeError,
     TimeLimitExceeded,
@@ -153,30 +295,77 @@ pub enum JudgeResultKind {
     SystemOutputLimitExceeded,
     SystemRuntimeError,
     SystemCompileError,
+
+    Skipped,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct JudgeCaseResult {
     pub kind: JudgeResultKind,
     pub time: u64,
     pub memory: u64,
+    /// the ratio of the full score, in `[0, 1]`
+    #[serde(default)]
+    pub score: Option<f64>,
+    /// the message from the special judge or the interactor
+    #[serde(default)]
+    pub message: Option<String>,
+    #[serde(default)]
+    pub diagnostics: Option<CaseDiagnostics>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// details of a failed case, whose excerpts are truncated
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CaseDiagnostics {
+    pub exit_code: Option<i32>,
+    pub signal: Option<i32>,
+    /// milliseconds
+    pub real_time: Option<u64>,
+    pub stdout: Option<String>,
+    pub stderr: Option<String>,
+    pub difference: Option<OutputDifference>,
+}
+
+/// the first differing line between the user output and the answer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OutputDifference {
+    /// starts from 1
+    pub line: u64,
+    pub user: String,
+    pub answer: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubtaskResult {
+    pub kind: JudgeResultKind,
+    pub score: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct JudgeResult {
     pub cases: Vec<JudgeCaseResult>,
     pub extra: Option<JudgeResultExtra>,
+    #[serde(default)]
+    pub subtasks: Option<Vec<SubtaskResult>>,
+    /// the total score of subtasks, or the average case score out of 100 without subtasks
+    #[serde(default)]
+    pub score: Option<f64>,
+    /// the cases discovered by the judger
+    #[serde(default)]
+    pub discovered_cases: Option<Vec<TestCase>>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct JudgeResultExtra {
     pub user: Option<ExecutionInfo>,
     pub spj: Option<ExecutionInfo>,
     pub interactive: Option<ExecutionInfo>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ExecutionInfo {
     pub compile_message: Option<String>,
@@ -11,6 +11,8 @@ pub enum ErrorCode {
     AlreadyConnected = 1004,
     SignatureMismatch = 1005,
     PermissionDenied = 1006,
+    IncompatibleProtocol = 1007,
+    NotFound = 1008,
 }
 
 impl ErrorCode {
@@ -23,6 +25,8 @@ impl ErrorCode {
             ErrorCode::AlreadyConnected => StatusCode::BAD_REQUEST,
             ErrorCode::SignatureMismatch => StatusCode::FORBIDDEN,
             ErrorCode::PermissionDenied => StatusCode::FORBIDDEN,
+            ErrorCode::IncompatibleProtocol => StatusCode::BAD_REQUEST,
+            ErrorCode::NotFound => StatusCode::NOT_FOUND,
         }
     }
 }
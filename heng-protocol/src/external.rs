@@ -1,4 +1,4 @@
-use crate::common::{DynamicFile, File, Judge, JudgeResult, JudgeState, Test};
+use crate::common::{DynamicFile, File, Judge, JudgeCaseResult, JudgeResult, JudgeState, Test};
 
 use serde::{Deserialize, Serialize};
 
@@ -10,6 +10,9 @@ pub struct CreateJudgeRequest {
     pub judge: Judge,
     pub test: Test,
     pub callback_urls: CallbackUrls,
+    /// reports diagnostics of failed cases
+    #[serde(default)]
+    pub diagnostics: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -18,10 +21,15 @@ pub struct CallbackUrls {
     pub finish: String,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct UpdateJudgeCallback {
     pub state: JudgeState,
+    pub current_case: Option<u32>,
+    pub cases: Option<Vec<JudgeCaseResult>>,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
 pub struct FinishJudgeCallback {
     pub result: JudgeResult,
 }
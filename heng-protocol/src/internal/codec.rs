@@ -0,0 +1,180 @@
+use super::ws_json::Message;
+use super::Feature;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+    /// text frames
+    Json,
+    /// binary frames
+    MessagePack,
+}
+
+pub enum Frame {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum CodecError {
+    #[error("json codec error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("msgpack encode error: {0}")]
+    MessagePackEncode(#[from] rmp_serde::encode::Error),
+    #[error("msgpack decode error: {0}")]
+    MessagePackDecode(#[from] rmp_serde::decode::Error),
+}
+
+impl Codec {
+    /// selects the codec for sending messages
+    pub fn negotiate(features: &[Feature]) -> Self {
+        if features.contains(&Feature::MessagePack) {
+            Codec::MessagePack
+        } else {
+            Codec::Json
+        }
+    }
+
+    pub fn encode(self, msg: &Message) -> Result<Frame, CodecError> {
+        match self {
+            Codec::Json => Ok(Frame::Text(serde_json::to_string(msg)?)),
+            Codec::MessagePack => Ok(Frame::Binary(rmp_serde::to_vec_named(msg)?)),
+        }
+    }
+}
+
+/// decodes a text frame, which is always json
+pub fn decode_text(text: &str) -> Result<Message, CodecError> {
+    Ok(serde_json::from_str(text)?)
+}
+
+/// decodes a binary frame, which is always msgpack
+pub fn decode_binary(bytes: &[u8]) -> Result<Message, CodecError> {
+    Ok(rmp_serde::from_slice(bytes)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::common::*;
+    use crate::internal::ws_json::{CreateJudgeArgs, FinishJudgeArgs, Request, Response};
+
+    use chrono::Utc;
+
+    fn decode(frame: &Frame) -> Message {
+        match frame {
+            Frame::Text(text) => decode_text(text).unwrap(),
+            Frame::Binary(bytes) => decode_binary(bytes).unwrap(),
+        }
+    }
+
+    fn roundtrip(msg: Message) {
+        let expected = serde_json::to_value(&msg).unwrap();
+        for &codec in &[Codec::Json, Codec::MessagePack] {
+            let frame = codec.encode(&msg).unwrap();
+            match (codec, &frame) {
+                (Codec::Json, Frame::Text(_)) | (Codec::MessagePack, Frame::Binary(_)) => {}
+                _ => panic!("unexpected frame type"),
+            }
+            let decoded = decode(&frame);
+            assert_eq!(serde_json::to_value(&decoded).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn create_judge() {
+        let source = File::Direct {
+            content: base64::encode(b"int main(){}"),
+            hashsum: None,
+            base64: true,
+        };
+        let args = CreateJudgeArgs {
+            id: "1".into(),
+            data: Some(File::Url {
+                url: "http://localhost/data.zip".into(),
+                hashsum: None,
+                headers: None,
+                token: Some("token".into()),
+            }),
+            dynamic_files: None,
+            judge: Judge::Normal {
+                user: Executable {
+                    source,
+                    environment: Default::default(),
+                    limit: Default::default(),
+                },
+            },
+            test: Test {
+                cases: vec![TestCase {
+                    input: "1.in".into(),
+                    output: "1.out".into(),
+                }],
+                policy: TestPolicy::Fuse,
+                subtasks: None,
+                discover: false,
+            },
+            diagnostics: false,
+        };
+        let args = args.into_binary_files();
+        match args.judge {
+            Judge::Normal { ref user } => match user.source {
+                File::Binary { ref content, .. } => assert_eq!(content, b"int main(){}"),
+                _ => panic!("expected binary file"),
+            },
+            _ => unreachable!(),
+        }
+        roundtrip(Message::Request {
+            seq: 1,
+            time: Utc::now(),
+            body: Request::CreateJudge(args),
+        });
+    }
+
+    #[test]
+    fn finish_judge() {
+        let result = JudgeResult {
+            cases: vec![JudgeCaseResult {
+                kind: JudgeResultKind::Accepted,
+                time: 1,
+                memory: 2,
+                score: Some(1.0),
+                message: Some("ok".into()),
+                diagnostics: None,
+            }],
+            extra: None,
+            subtasks: Some(vec![SubtaskResult {
+                kind: JudgeResultKind::Accepted,
+                score: 100.0,
+            }]),
+            score: Some(100.0),
+            discovered_cases: None,
+        };
+        roundtrip(Message::Request {
+            seq: 2,
+            time: Utc::now(),
+            body: Request::FinishJudge(FinishJudgeArgs {
+                id: "1".into(),
+                result,
+            }),
+        });
+        roundtrip(Message::Response {
+            seq: 2,
+            time: Utc::now(),
+            body: Response::output(&serde_json::json!({ "statusReportInterval": 1000 })),
+        });
+    }
+
+    #[test]
+    fn drain() {
+        let msg = Message::Request {
+            seq: 3,
+            time: Utc::now(),
+            body: Request::Drain,
+        };
+        assert_eq!(
+            serde_json::to_value(&msg).unwrap()["body"]["method"],
+            "Drain"
+        );
+        roundtrip(msg);
+    }
+}
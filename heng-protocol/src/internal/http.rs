@@ -1,3 +1,5 @@
+use super::Feature;
+
 use serde::{Deserialize, Serialize};
 use validator::Validate;
 
@@ -7,6 +9,10 @@ pub struct AcquireTokenRequest {
     #[validate(range(min = 1, max = 64))]
     pub max_task_count: u32,
 
+    /// a random id of the judger process, which is kept across reconnects
+    #[validate(length(min = 1, max = 64))]
+    pub instance_id: String,
+
     #[validate(length(max = 256))]
     pub name: Option<String>,
 
@@ -14,9 +20,30 @@ pub struct AcquireTokenRequest {
 
     #[validate(length(max = 256))]
     pub software: Option<String>,
+
+    /// judgers without this field speak an unversioned protocol
+    #[serde(default)]
+    pub protocol_version: u32,
+
+    #[serde(default)]
+    #[validate(length(max = 64))]
+    pub features: Vec<Feature>,
+
+    /// languages which passed the self-test, or unknown if absent
+    #[serde(default)]
+    #[validate(length(max = 64))]
+    pub languages: Option<Vec<String>>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct AcquireTokenOutput {
     pub token: String,
+
+    #[serde(default)]
+    pub protocol_version: u32,
+
+    /// the negotiated features, which are supported by both sides
+    #[serde(default)]
+    pub features: Vec<Feature>,
 }
@@ -1,12 +1,72 @@
+use crate::common;
 use crate::error::ErrorCode;
 
 use std::fmt;
 
 use serde::{Deserialize, Serialize};
 
+pub mod codec;
 pub mod http;
+pub mod rpc;
 pub mod ws_json;
 
+/// the version of the internal protocol implemented by this crate
+///
+/// The version is bumped by every change to the shape of messages,
+/// unless the new shape is only sent when a [`Feature`] is negotiated,
+/// such as `File::Binary` with [`Feature::MessagePack`]
+/// and `Request::Drain` with [`Feature::Drain`].
+///
+/// 1. versions and features are negotiated when acquiring a token,
+///    and `StatusReport` has `[f32; 3]` load averages
+/// 2. `Test.subtasks`, `JudgeResult.subtasks`, `JudgeResult.score` and `JudgeCaseResult.score`
+/// 3. `JudgeCaseResult.message`
+/// 4. `CreateJudgeArgs.diagnostics` and `JudgeCaseResult.diagnostics`
+/// 5. `Test.discover` and `JudgeResult.discovered_cases`
+/// 6. `File::Stored`
+/// 7. `AcquireTokenRequest.instance_id`
+pub const PROTOCOL_VERSION: u32 = 7;
+
+/// the oldest protocol version which is still compatible with [`PROTOCOL_VERSION`]
+pub const MIN_PROTOCOL_VERSION: u32 = 7;
+
+pub fn is_compatible_version(version: u32) -> bool {
+    (MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&version)
+}
+
+/// optional capabilities which are negotiated when a judger acquires its token
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Feature {
+    /// `ReportStatusArgs.report` carries a typed `StatusReport`
+    StatusReport,
+
+    /// rpc messages can be sent as msgpack binary frames
+    MessagePack,
+
+    /// `UpdateJudgeArgs` carries the progress of finished cases
+    JudgeProgress,
+
+    /// a judger can ask the controller to stop dispatching judges to it
+    Drain,
+
+    /// features from a newer peer which are unknown to this side
+    #[serde(other)]
+    Unknown,
+}
+
+/// returns the features supported by both sides
+pub fn negotiate_features(local: &[Feature], remote: &[Feature]) -> Vec<Feature> {
+    let mut ans: Vec<Feature> = local
+        .iter()
+        .copied()
+        .filter(|f| *f != Feature::Unknown && remote.contains(f))
+        .collect();
+    ans.sort_unstable();
+    ans.dedup();
+    ans
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ConnectionSettings {
@@ -17,6 +77,9 @@ pub struct ConnectionSettings {
 #[serde(rename_all = "camelCase")]
 pub struct PartialConnectionSettings {
     pub status_report_interval: Option<u64>, // milliseconds
+    /// runs the self-test again, then the judger logs in again if its languages change
+    #[serde(default)]
+    pub self_test: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, thiserror::Error)]
@@ -40,3 +103,36 @@ pub enum JudgeState {
     Judgeing,
     Finished,
 }
+
+impl From<JudgeState> for common::JudgeState {
+    fn from(state: JudgeState) -> Self {
+        match state {
+            JudgeState::Confirmed => common::JudgeState::Confirmed,
+            JudgeState::Pending => common::JudgeState::Pending,
+            JudgeState::Preparing => common::JudgeState::Preparing,
+            JudgeState::Judgeing => common::JudgeState::Judging,
+            JudgeState::Finished => common::JudgeState::Finished,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negotiate() {
+        let local = [Feature::Drain, Feature::StatusReport, Feature::Drain];
+        let remote = [
+            Feature::StatusReport,
+            Feature::Unknown,
+            Feature::Drain,
+            Feature::StatusReport,
+        ];
+        assert_eq!(
+            negotiate_features(&local, &remote),
+            [Feature::StatusReport, Feature::Drain]
+        );
+        assert!(negotiate_features(&local, &[Feature::Unknown]).is_empty());
+    }
+}
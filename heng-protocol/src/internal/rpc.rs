@@ -0,0 +1,311 @@
+use super::ws_json::{Message, Request, Response};
+use super::ErrorInfo;
+
+use std::collections::hash_map::{Entry, HashMap};
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering::Relaxed};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::Utc;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use tokio::sync::{mpsc, oneshot, Semaphore};
+use tokio::{task, time};
+use tracing::warn;
+
+#[derive(Debug, Clone)]
+pub struct RpcConfig {
+    pub timeout: Duration,
+    pub max_inflight: usize,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RpcError {
+    #[error("rpc timeout")]
+    Timeout,
+    #[error("rpc session is closed")]
+    Closed,
+    #[error("rpc remote error: {0}")]
+    Remote(ErrorInfo),
+    #[error("rpc invalid output: {0}")]
+    InvalidOutput(serde_json::Error),
+}
+
+/// A transport-agnostic rpc session.
+///
+/// Outgoing messages are pushed into `sender`.
+/// Incoming messages should be fed into [`RpcSession::dispatch`].
+pub struct RpcSession {
+    config: RpcConfig,
+    seq: AtomicU32,
+    callbacks: Mutex<HashMap<u32, oneshot::Sender<Response>>>,
+    sender: mpsc::Sender<Message>,
+    inflight: Semaphore,
+    closed: AtomicBool,
+}
+
+/// removes the callback when a call is finished or cancelled
+struct CallGuard<'a> {
+    session: &'a RpcSession,
+    seq: u32,
+}
+
+impl Drop for CallGuard<'_> {
+    fn drop(&mut self) {
+        let mut callbacks = self.session.callbacks.lock().unwrap();
+        let _ = callbacks.remove(&self.seq);
+    }
+}
+
+impl RpcSession {
+    pub fn new(sender: mpsc::Sender<Message>, config: RpcConfig) -> Self {
+        Self {
+            inflight: Semaphore::new(config.max_inflight),
+            config,
+            seq: AtomicU32::new(0),
+            callbacks: Mutex::new(HashMap::new()),
+            sender,
+            closed: AtomicBool::new(false),
+        }
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Relaxed)
+    }
+
+    /// Closes the session. All pending calls fail with [`RpcError::Closed`].
+    pub fn close(&self) {
+        self.closed.store(true, Relaxed);
+        self.inflight.close();
+        let mut callbacks = self.callbacks.lock().unwrap();
+        callbacks.clear();
+    }
+
+    /// Sends a request and waits for its response.
+    ///
+    /// Dropping the returned future cancels the call.
+    pub async fn call(&self, req: Request) -> Result<Response, RpcError> {
+        if self.is_closed() {
+            return Err(RpcError::Closed);
+        }
+
+        let deadline = time::Instant::now() + self.config.timeout;
+
+        let _permit = match time::timeout_at(deadline, self.inflight.acquire()).await {
+            Ok(Ok(permit)) => permit,
+            Ok(Err(_)) => return Err(RpcError::Closed),
+            Err(_) => return Err(RpcError::Timeout),
+        };
+
+        let (seq, rx) = self.register()?;
+        let _guard = CallGuard { session: self, seq };
+
+        let msg = Message::Request {
+            seq,
+            time: Utc::now(),
+            body: req,
+        };
+        if self.sender.send(msg).await.is_err() {
+            return Err(RpcError::Closed);
+        }
+
+        match time::timeout_at(deadline, rx).await {
+            Ok(Ok(res)) => Ok(res),
+            Ok(Err(_)) => Err(RpcError::Closed),
+            Err(_) => Err(RpcError::Timeout),
+        }
+    }
+
+    /// Sends a request and deserializes its output.
+    pub async fn request<T: DeserializeOwned>(&self, req: Request) -> Result<T, RpcError> {
+        match self.call(req).await? {
+            Response::Output(output) => {
+                let value = output.unwrap_or(Value::Null);
+                serde_json::from_value(value).map_err(RpcError::InvalidOutput)
+            }
+            Response::Error(err) => Err(RpcError::Remote(err)),
+        }
+    }
+
+    pub async fn respond(&self, seq: u32, body: Response) -> Result<(), RpcError> {
+        let msg = Message::Response {
+            seq,
+            time: Utc::now(),
+            body,
+        };
+        self.sender.send(msg).await.map_err(|_| RpcError::Closed)
+    }
+
+    /// Handles an incoming message.
+    ///
+    /// A request is handled by `handler` in a new task and its response is sent back.
+    /// A response is delivered to the pending call.
+    pub fn dispatch<H, F>(self: &Arc<Self>, msg: Message, handler: &H)
+    where
+        H: Fn(Request) -> F,
+        F: Future<Output = Response> + Send + 'static,
+    {
+        match msg {
+            Message::Request { seq, body, .. } => {
+                let future = handler(body);
+                let this = Arc::clone(self);
+                task::spawn(async move {
+                    let response = future.await;
+                    if this.respond(seq, response).await.is_err() {
+                        warn!(?seq, "failed to respond: the session is closed");
+                    }
+                });
+            }
+            Message::Response { seq, body, .. } => {
+                let cb = self.callbacks.lock().unwrap().remove(&seq);
+                match cb {
+                    None => warn!(?seq, "no such callback"),
+                    Some(cb) => {
+                        if cb.send(body).is_err() {
+                            warn!(?seq, "the callback is cancelled");
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn register(&self) -> Result<(u32, oneshot::Receiver<Response>), RpcError> {
+        let mut callbacks = self.callbacks.lock().unwrap();
+        if self.is_closed() {
+            return Err(RpcError::Closed);
+        }
+        // skips the seqs which are still in use after wraparound
+        loop {
+            let seq = self.seq.fetch_add(1, Relaxed).wrapping_add(1);
+            if let Entry::Vacant(entry) = callbacks.entry(seq) {
+                let (tx, rx) = oneshot::channel();
+                entry.insert(tx);
+                return Ok((seq, rx));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::error::ErrorCode;
+    use crate::internal::{ConnectionSettings, PartialConnectionSettings};
+
+    fn config(timeout: u64, max_inflight: usize) -> RpcConfig {
+        RpcConfig {
+            timeout: Duration::from_millis(timeout),
+            max_inflight,
+        }
+    }
+
+    /// connects two sessions with in-memory channels
+    fn duplex<H, F>(cfg: RpcConfig, handler: H) -> (Arc<RpcSession>, Arc<RpcSession>)
+    where
+        H: Fn(Request) -> F + Send + 'static,
+        F: Future<Output = Response> + Send + 'static,
+    {
+        let (a_tx, mut a_rx) = mpsc::channel(16);
+        let (b_tx, mut b_rx) = mpsc::channel(16);
+        let a = Arc::new(RpcSession::new(a_tx, cfg.clone()));
+        let b = Arc::new(RpcSession::new(b_tx, cfg));
+
+        {
+            let b = b.clone();
+            task::spawn(async move {
+                while let Some(msg) = a_rx.recv().await {
+                    b.dispatch(msg, &handler);
+                }
+            });
+        }
+        {
+            let a = a.clone();
+            let handler = |_| async { Response::error(ErrorCode::NotSupported, None) };
+            task::spawn(async move {
+                while let Some(msg) = b_rx.recv().await {
+                    a.dispatch(msg, &handler);
+                }
+            });
+        }
+
+        (a, b)
+    }
+
+    #[tokio::test]
+    async fn typed_request() {
+        let handler = |req| async move {
+            match req {
+                Request::Control(Some(settings)) => Response::output(&ConnectionSettings {
+                    status_report_interval: settings.status_report_interval.unwrap_or(1000),
+                }),
+                _ => Response::error(ErrorCode::NotSupported, None),
+            }
+        };
+        let (a, _b) = duplex(config(1000, 4), handler);
+
+        let req = Request::Control(Some(PartialConnectionSettings {
+            status_report_interval: Some(500),
+            self_test: false,
+        }));
+        let settings: ConnectionSettings = a.request(req).await.unwrap();
+        assert_eq!(settings.status_report_interval, 500);
+
+        let err = a.request::<()>(Request::Control(None)).await.unwrap_err();
+        assert!(matches!(err, RpcError::Remote(e) if e.code == ErrorCode::NotSupported));
+    }
+
+    #[tokio::test]
+    async fn timeout_and_inflight_limit() {
+        let handler = |_| async {
+            time::sleep(Duration::from_millis(200)).await;
+            Response::null()
+        };
+        let (a, _b) = duplex(config(100, 1), handler);
+
+        let first = a.request::<()>(Request::Control(None));
+        let second = a.request::<()>(Request::Control(None));
+        let (first, second) = tokio::join!(first, second);
+        assert!(matches!(first, Err(RpcError::Timeout)));
+        assert!(matches!(second, Err(RpcError::Timeout)));
+        assert!(a.callbacks.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn close_pending_calls() {
+        let handler = |_| async {
+            time::sleep(Duration::from_millis(1000)).await;
+            Response::null()
+        };
+        let (a, _b) = duplex(config(5000, 4), handler);
+
+        let call = {
+            let a = a.clone();
+            task::spawn(async move { a.request::<()>(Request::Control(None)).await })
+        };
+        time::sleep(Duration::from_millis(50)).await;
+        a.close();
+
+        assert!(matches!(call.await.unwrap(), Err(RpcError::Closed)));
+        assert!(matches!(
+            a.request::<()>(Request::Control(None)).await,
+            Err(RpcError::Closed)
+        ));
+    }
+
+    #[test]
+    fn seq_wraparound() {
+        let (tx, _rx) = mpsc::channel(1);
+        let session = RpcSession::new(tx, config(1000, 4));
+
+        session.seq.store(u32::MAX, Relaxed);
+        let (seq0, _rx0) = session.register().unwrap();
+        assert_eq!(seq0, 0);
+
+        session.seq.store(u32::MAX, Relaxed);
+        let (seq1, _rx1) = session.register().unwrap();
+        assert_eq!(seq1, 1);
+    }
+}
@@ -1,11 +1,14 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use serde_json::value::RawValue;
+use serde_json::Value;
 
-use crate::common::{DynamicFile, File, Judge, JudgeResult, Test};
+use crate::common::{DynamicFile, File, Judge, JudgeCaseResult, JudgeResult, StatusReport, Test};
+use crate::error::ErrorCode;
 
 use super::{ErrorInfo, JudgeState, PartialConnectionSettings};
 
+use std::mem;
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum Message {
@@ -31,16 +34,33 @@ pub enum Request {
     ReportStatus(ReportStatusArgs),
     UpdateJudge(UpdateJudgeArgs),
     FinishJudge(FinishJudgeArgs),
+    /// the judger is shutting down and accepts no more judges
+    Drain,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub enum Response {
     #[serde(rename = "output")]
-    Output(Option<Box<RawValue>>),
+    Output(Option<Value>),
     #[serde(rename = "error")]
     Error(ErrorInfo),
 }
 
+impl Response {
+    pub fn null() -> Self {
+        Response::Output(None)
+    }
+
+    pub fn output<T: Serialize>(value: &T) -> Self {
+        let value = serde_json::to_value(value).unwrap();
+        Response::Output(Some(value))
+    }
+
+    pub fn error(code: ErrorCode, message: Option<String>) -> Self {
+        Response::Error(ErrorInfo { code, message })
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct CreateJudgeArgs {
@@ -49,6 +69,36 @@ pub struct CreateJudgeArgs {
     pub dynamic_files: Option<Vec<DynamicFile>>,
     pub judge: Judge,
     pub test: Test,
+    #[serde(default)]
+    pub diagnostics: bool,
+}
+
+impl CreateJudgeArgs {
+    /// converts all direct files into raw bytes for binary codecs
+    pub fn into_binary_files(mut self) -> Self {
+        fn convert(file: &mut File) {
+            let placeholder = File::Binary {
+                content: Vec::new(),
+                hashsum: None,
+            };
+            *file = mem::replace(file, placeholder).into_binary();
+        }
+
+        if let Some(ref mut data) = self.data {
+            convert(data);
+        }
+        if let Some(ref mut dyn_files) = self.dynamic_files {
+            for dyn_file in dyn_files {
+                if let DynamicFile::Remote { ref mut file, .. } = dyn_file {
+                    convert(file);
+                }
+            }
+        }
+        for exe in self.judge.executables_mut() {
+            convert(&mut exe.source);
+        }
+        self
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -56,16 +106,25 @@ pub struct CreateJudgeArgs {
 pub struct ReportStatusArgs {
     pub collect_time: DateTime<Utc>,
     pub next_report_time: DateTime<Utc>,
-    pub report: Option<Box<RawValue>>, // FIXME: define type StatusReport
+    pub report: Option<StatusReport>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct UpdateJudgeArgs {
     pub id: String,
     pub state: JudgeState,
+
+    /// the index of the case which is being judged
+    #[serde(default)]
+    pub current_case: Option<u32>,
+
+    /// the results of finished cases
+    #[serde(default)]
+    pub cases: Option<Vec<JudgeCaseResult>>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FinishJudgeArgs {
     pub id: String,
     pub result: JudgeResult,
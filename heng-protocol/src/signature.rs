@@ -65,6 +65,24 @@ pub fn calc_signature(
     headers: &http::HeaderMap,
     body: &[u8],
     secret_key: &str,
+) -> String {
+    let body_hash = if body.is_empty() {
+        EMPTY_SHA256_HASH.to_owned()
+    } else {
+        hex_sha256(body)
+    };
+    calc_signature_with_body_hash(method, path, query, headers, &body_hash, secret_key)
+}
+
+/// calculates the signature with the sha256 hex of the body,
+/// which can be hashed while the body is streamed
+pub fn calc_signature_with_body_hash(
+    method: &http::Method,
+    path: &str,
+    query: &str,
+    headers: &http::HeaderMap,
+    body_hash: &str,
+    secret_key: &str,
 ) -> String {
     let mut request_string = String::new();
 
@@ -106,11 +124,7 @@ pub fn calc_signature(
         request_string.push('\n');
     }
     {
-        if body.is_empty() {
-            request_string += EMPTY_SHA256_HASH;
-        } else {
-            request_string += &hex_sha256(body);
-        }
+        request_string += body_hash;
         request_string.push('\n');
     }
     hex_hmac_sha256(secret_key.as_bytes(), request_string.as_bytes())
@@ -120,7 +134,8 @@ pub fn calc_signature(
 mod tests {
     use http::header::{HeaderName, HeaderValue};
 
-    use super::calc_signature;
+    use super::{calc_signature, calc_signature_with_body_hash};
+    use heng_utils::crypto::hex_sha256;
 
     macro_rules! hname {
         ($str:literal) => {
@@ -155,4 +170,22 @@ mod tests {
             "5a9b2583678fd88de7ebb5a422ba3d5f6475ab729b892aa05b94c302b79bee1e"
         )
     }
+
+    #[test]
+    fn body_hash() {
+        let map = http::HeaderMap::new();
+        let body = b"1 2";
+        let path = "/v1/files/blake3-0000";
+        assert_eq!(
+            calc_signature(&http::Method::PUT, path, "", &map, body, "sk"),
+            calc_signature_with_body_hash(
+                &http::Method::PUT,
+                path,
+                "",
+                &map,
+                &hex_sha256(body),
+                "sk"
+            )
+        );
+    }
 }
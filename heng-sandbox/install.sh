@@ -0,0 +1,9 @@
+# run this script in the heng-sandbox directory
+
+# build heng-sandbox and install it to /usr/local/bin
+
+set -e
+
+cargo build --release
+
+sudo install -m 755 ../target/release/heng-sandbox /usr/local/bin/heng-sandbox
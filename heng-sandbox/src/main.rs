@@ -0,0 +1,110 @@
+use heng_judger::lang::{self, Limit};
+use heng_judger::sandbox::{Carapace, Command};
+use heng_judger::Config;
+
+use std::env;
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use structopt::StructOpt;
+use ubyte::ByteUnit;
+
+const CONFIG_PATH: &str = "heng-judger.toml";
+
+/// runs a command with the sandbox policy of heng-judger
+///
+/// Limits are the hard limits of the config by default, and are clamped by them.
+#[derive(Debug, StructOpt)]
+#[structopt(name = "heng-sandbox")]
+struct Opt {
+    /// the config file of heng-judger, which provides uid, gid and hard limits
+    #[structopt(long, default_value = CONFIG_PATH)]
+    config: PathBuf,
+
+    /// the root directory of the command, which is the current directory by default
+    #[structopt(long)]
+    workspace: Option<PathBuf>,
+
+    /// the real time limit in milliseconds
+    #[structopt(long)]
+    real_time: Option<u64>,
+
+    /// the cpu time limit in milliseconds
+    #[structopt(long)]
+    cpu_time: Option<u64>,
+
+    /// the memory limit, such as "256MiB"
+    #[structopt(long)]
+    memory: Option<ByteUnit>,
+
+    /// the output limit, such as "64MiB"
+    #[structopt(long)]
+    output: Option<ByteUnit>,
+
+    /// the max number of processes
+    #[structopt(long)]
+    pids: Option<u32>,
+
+    /// the stdin file in the workspace
+    #[structopt(long, requires_all = &["stdout", "stderr"])]
+    stdin: Option<PathBuf>,
+
+    /// the stdout file in the workspace
+    #[structopt(long, requires_all = &["stdin", "stderr"])]
+    stdout: Option<PathBuf>,
+
+    /// the stderr file in the workspace
+    #[structopt(long, requires_all = &["stdin", "stdout"])]
+    stderr: Option<PathBuf>,
+
+    /// a read-only bind mount as "SRC" or "SRC:DST", besides the default ones
+    #[structopt(long = "mount", number_of_values = 1)]
+    mounts: Vec<String>,
+
+    /// the command and its arguments
+    #[structopt(required = true, last = true, parse(from_os_str))]
+    command: Vec<OsString>,
+}
+
+fn main() -> Result<()> {
+    let opt = Opt::from_args();
+    let config = Config::from_file(&opt.config)
+        .with_context(|| format!("failed to load config: path = {}", opt.config.display()))?;
+
+    let workspace = match opt.workspace {
+        Some(workspace) => workspace,
+        None => env::current_dir()?,
+    };
+
+    let hard_limit = &config.executor.hard_limit;
+    let limit = Limit {
+        real_time: opt.real_time.unwrap_or(hard_limit.real_time),
+        cpu_time: opt.cpu_time.unwrap_or(hard_limit.cpu_time),
+        memory: opt.memory.unwrap_or(hard_limit.memory).as_u64(),
+        output: opt.output.unwrap_or(hard_limit.output).as_u64(),
+        pids: opt.pids.unwrap_or(hard_limit.pids),
+    };
+
+    let mut cmd = Command::new(&opt.command[0]);
+    for arg in &opt.command[1..] {
+        cmd.arg(arg);
+    }
+    if let (Some(stdin), Some(stdout), Some(stderr)) = (opt.stdin, opt.stdout, opt.stderr) {
+        cmd.stdio(stdin, stdout, stderr);
+    }
+    for mount in &opt.mounts {
+        let mut iter = mount.splitn(2, ':');
+        let src = iter.next().unwrap_or_default();
+        let dst = iter.next().unwrap_or(src);
+        if src.is_empty() || dst.is_empty() {
+            anyhow::bail!("invalid mount: {}", mount);
+        }
+        cmd.bindmount_ro(src, dst);
+    }
+
+    let sandbox = Carapace::new(&config);
+    let output = lang::sandbox_run(&sandbox, &cmd, &config, &workspace, &limit)?;
+    println!("{}", serde_json::to_string_pretty(&output)?);
+    Ok(())
+}
@@ -1,4 +1,8 @@
+use std::fmt;
+use std::io;
+
 use hmac::{Hmac, Mac, NewMac};
+use md5::Md5;
 use sha2::{Digest, Sha256};
 
 pub fn to_hex_string(src: &[u8]) -> String {
@@ -25,3 +29,199 @@ pub fn is_hex_sha256_format(s: &str) -> bool {
     let bytes = s.as_bytes();
     bytes.len() == 64 && bytes.iter().all(u8::is_ascii_hexdigit)
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    Sha256,
+    Blake3,
+    Md5,
+}
+
+impl HashAlgorithm {
+    pub fn name(self) -> &'static str {
+        match self {
+            HashAlgorithm::Sha256 => "sha256",
+            HashAlgorithm::Blake3 => "blake3",
+            HashAlgorithm::Md5 => "md5",
+        }
+    }
+
+    /// whether content can be addressed by its hashsum
+    ///
+    /// Md5 is not collision resistant, so it only verifies content.
+    pub fn is_addressable(self) -> bool {
+        match self {
+            HashAlgorithm::Sha256 | HashAlgorithm::Blake3 => true,
+            HashAlgorithm::Md5 => false,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "sha256" => Some(HashAlgorithm::Sha256),
+            "blake3" => Some(HashAlgorithm::Blake3),
+            "md5" => Some(HashAlgorithm::Md5),
+            _ => None,
+        }
+    }
+
+    /// length of the hex digest
+    pub fn hex_len(self) -> usize {
+        match self {
+            HashAlgorithm::Sha256 | HashAlgorithm::Blake3 => 64,
+            HashAlgorithm::Md5 => 32,
+        }
+    }
+}
+
+/// a hashsum with an algorithm prefix, such as `blake3:<hex>`
+///
+/// Bare hex is treated as sha256 for compatibility.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hashsum<'a> {
+    pub algorithm: HashAlgorithm,
+    pub hex: &'a str,
+}
+
+impl<'a> Hashsum<'a> {
+    pub fn parse(s: &'a str) -> Option<Self> {
+        Self::parse_with(s, ':')
+    }
+
+    /// parses a name returned by [`Hashsum::file_name`]
+    pub fn parse_file_name(s: &'a str) -> Option<Self> {
+        Self::parse_with(s, '-')
+    }
+
+    fn parse_with(s: &'a str, sep: char) -> Option<Self> {
+        let (algorithm, hex) = match s.find(sep) {
+            Some(idx) => (HashAlgorithm::from_name(&s[..idx])?, &s[idx + 1..]),
+            None => (HashAlgorithm::Sha256, s),
+        };
+        let bytes = hex.as_bytes();
+        let valid = bytes.len() == algorithm.hex_len()
+            && bytes
+                .iter()
+                .all(|&b| matches!(b, b'0'..=b'9' | b'a'..=b'f'));
+        if valid {
+            Some(Self { algorithm, hex })
+        } else {
+            None
+        }
+    }
+
+    /// a name which is safe in paths and urls
+    ///
+    /// Sha256 hashsums are named by bare hex, so that existing names are kept.
+    pub fn file_name(&self) -> String {
+        match self.algorithm {
+            HashAlgorithm::Sha256 => self.hex.to_owned(),
+            algorithm => format!("{}-{}", algorithm.name(), self.hex),
+        }
+    }
+}
+
+impl fmt::Display for Hashsum<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.algorithm.name(), self.hex)
+    }
+}
+
+/// a streaming hasher of any supported algorithm
+pub enum Hasher {
+    Sha256(Sha256),
+    Blake3(Box<blake3::Hasher>),
+    Md5(Md5),
+}
+
+impl Hasher {
+    pub fn new(algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+            HashAlgorithm::Blake3 => Hasher::Blake3(Box::new(blake3::Hasher::new())),
+            HashAlgorithm::Md5 => Hasher::Md5(Md5::new()),
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Sha256(h) => Digest::update(h, data),
+            Hasher::Blake3(h) => {
+                h.update(data);
+            }
+            Hasher::Md5(h) => Digest::update(h, data),
+        }
+    }
+
+    pub fn finalize_hex(self) -> String {
+        match self {
+            Hasher::Sha256(h) => to_hex_string(h.finalize().as_ref()),
+            Hasher::Blake3(h) => to_hex_string(blake3::Hasher::finalize(&h).as_bytes()),
+            Hasher::Md5(h) => to_hex_string(h.finalize().as_ref()),
+        }
+    }
+}
+
+impl io::Write for Hasher {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+pub fn hex_digest(algorithm: HashAlgorithm, data: &[u8]) -> String {
+    let mut hasher = Hasher::new(algorithm);
+    hasher.update(data);
+    hasher.finalize_hex()
+}
+
+/// hashes a reader in a streaming way
+pub fn hex_digest_reader(
+    algorithm: HashAlgorithm,
+    reader: &mut impl io::Read,
+) -> io::Result<String> {
+    let mut hasher = Hasher::new(algorithm);
+    io::copy(reader, &mut hasher)?;
+    Ok(hasher.finalize_hex())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashsum() {
+        let sha256 = hex_sha256(b"abc");
+        let h = Hashsum::parse(&sha256).unwrap();
+        assert_eq!(h.algorithm, HashAlgorithm::Sha256);
+        assert_eq!(h.file_name(), sha256);
+        assert_eq!(Hashsum::parse(&format!("sha256:{}", sha256)), Some(h));
+
+        let md5 = format!("md5:{}", hex_digest(HashAlgorithm::Md5, b"abc"));
+        assert_eq!(md5, "md5:900150983cd24fb0d6963f7d28e17f72");
+        let h = Hashsum::parse(&md5).unwrap();
+        assert_eq!(h.to_string(), md5);
+        assert_eq!(h.file_name(), "md5-900150983cd24fb0d6963f7d28e17f72");
+        assert_eq!(Hashsum::parse_file_name(&h.file_name()), Some(h));
+
+        let blake3 = hex_digest(HashAlgorithm::Blake3, b"abc");
+        assert_eq!(
+            blake3,
+            "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"
+        );
+        let mut reader: &[u8] = b"abc";
+        assert_eq!(
+            hex_digest_reader(HashAlgorithm::Blake3, &mut reader).unwrap(),
+            blake3
+        );
+
+        assert!(Hashsum::parse("crc32:00000000").is_none());
+        assert!(Hashsum::parse(&format!("md5:{}", sha256)).is_none());
+        assert!(Hashsum::parse(&sha256.to_uppercase()).is_none());
+        assert!(Hashsum::parse_file_name(&md5).is_none());
+    }
+}
@@ -7,4 +7,7 @@ pub mod math;
 pub mod os_cmd;
 pub mod queue;
 pub mod result;
+pub mod signal;
+#[cfg(feature = "test-util")]
+pub mod temp_dir;
 pub mod tracing;
@@ -16,4 +16,12 @@ impl<T: Send> Queue<T> {
     pub async fn pop(&self) -> T {
         self.rx.recv().await.unwrap()
     }
+
+    pub fn len(&self) -> usize {
+        self.rx.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rx.is_empty()
+    }
 }
@@ -0,0 +1,12 @@
+use tokio::signal::unix::{signal, SignalKind};
+use tracing::info;
+
+/// resolves when the process receives SIGINT or SIGTERM
+pub async fn shutdown() {
+    let mut sigint = signal(SignalKind::interrupt()).expect("failed to listen SIGINT");
+    let mut sigterm = signal(SignalKind::terminate()).expect("failed to listen SIGTERM");
+    tokio::select! {
+        _ = sigint.recv() => info!("received SIGINT"),
+        _ = sigterm.recv() => info!("received SIGTERM"),
+    }
+}